@@ -0,0 +1,76 @@
+//! Bytes per retained transaction, of the former `HashMap<TxnId, Txn>` and of `TxnTable`.
+//! Run with `cargo bench --bench memory`.
+use payments_engine::{
+    txn::{Txn, TxnType},
+    txn_table::TxnTable,
+    types::TxnId,
+};
+use rust_decimal::Decimal;
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    collections::HashMap,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Tracks bytes currently allocated.
+struct CountingAlloc;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+fn txn(i: u32) -> Txn {
+    let txn_type = match i % 3 {
+        0 => TxnType::Withdrawal,
+        _ => TxnType::Deposit,
+    };
+    // amounts of up to 4 decimal places, as per the input spec
+    Txn::new(
+        txn_type,
+        Decimal::new(i64::from(i % 100_000) + 1, i % 5),
+        Some(u64::from(i)),
+    )
+}
+
+/// Bytes allocated by the container per transaction.
+fn bytes_per_txn<T>(count: u32, build: impl Fn(u32) -> T) -> f64 {
+    let before = ALLOCATED.load(Ordering::Relaxed);
+    let container = build(count);
+    let bytes = ALLOCATED.load(Ordering::Relaxed) - before;
+    drop(container);
+    bytes as f64 / f64::from(count)
+}
+
+fn main() {
+    println!("txns,hash_map_bytes_per_txn,txn_table_bytes_per_txn");
+    for count in [1_000, 100_000, 1_000_000] {
+        let hash_map = bytes_per_txn(count, |count| {
+            let mut txns = HashMap::<TxnId, Txn>::new();
+            for i in 0..count {
+                txns.insert(i, txn(i));
+            }
+            txns
+        });
+        let txn_table = bytes_per_txn(count, |count| {
+            let mut txns = TxnTable::default();
+            for i in 0..count {
+                txns.insert(i, txn(i));
+            }
+            txns
+        });
+        println!("{count},{hash_map:.1},{txn_table:.1}");
+    }
+}
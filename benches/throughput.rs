@@ -0,0 +1,146 @@
+//! Throughput of parsing and processing synthetic feeds, run with `cargo bench --bench throughput`.
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use payments_engine::{
+    generator::{generate_feed, FeedConfig},
+    payment_engine::{InMemoryPaymentEngine, PaymentEngine},
+    pipeline::{read_csv_parallel, PipelineOptions},
+    types::TxnEvent,
+    util::read_csv_file,
+};
+use std::hint::black_box;
+
+const EVENTS: u64 = 100_000;
+
+/// Realistic mixes of events, by name.
+fn mixes() -> Vec<(&'static str, FeedConfig)> {
+    let config = FeedConfig {
+        events: EVENTS,
+        ..Default::default()
+    };
+    vec![
+        (
+            "deposits",
+            FeedConfig {
+                dispute_ratio: 0.0,
+                withdrawal_ratio: 0.0,
+                ..config
+            },
+        ),
+        ("default", config),
+        (
+            "dispute_heavy",
+            FeedConfig {
+                dispute_ratio: 0.2,
+                resolve_ratio: 0.5,
+                ..config
+            },
+        ),
+    ]
+}
+
+fn feed(config: &FeedConfig) -> Vec<u8> {
+    let mut feed = vec![];
+    generate_feed(config, &mut feed).expect("writing to memory");
+    feed
+}
+
+fn parse(c: &mut Criterion) {
+    let feed = feed(&FeedConfig {
+        events: EVENTS,
+        ..Default::default()
+    });
+    let mut group = c.benchmark_group("parse");
+    group.throughput(Throughput::Elements(EVENTS));
+    group.bench_function("csv_records", |b| {
+        b.iter(|| {
+            let mut reader = csv::Reader::from_reader(feed.as_slice());
+            let mut record = csv::ByteRecord::new();
+            let mut count = 0;
+            while reader.read_byte_record(&mut record).expect("valid csv") {
+                count += 1;
+            }
+            black_box(count)
+        })
+    });
+    group.bench_function("read_csv_file", |b| {
+        b.iter(|| black_box(read_csv_file(feed.as_slice()).filter(Result::is_ok).count()))
+    });
+    group.bench_function("read_csv_parallel", |b| {
+        b.iter(|| {
+            let events = read_csv_parallel(std::io::Cursor::new(feed.clone()), Default::default());
+            black_box(events.filter(Result::is_ok).count())
+        })
+    });
+    group.finish();
+}
+
+fn deserialize(c: &mut Criterion) {
+    let feed = feed(&FeedConfig {
+        events: EVENTS,
+        ..Default::default()
+    });
+    let records = csv::Reader::from_reader(feed.as_slice())
+        .into_byte_records()
+        .collect::<Result<Vec<_>, _>>()
+        .expect("valid csv");
+    let headers = csv::ByteRecord::from(vec!["type", "client", "tx", "amount"]);
+    let mut group = c.benchmark_group("deserialize");
+    group.throughput(Throughput::Elements(records.len() as u64));
+    group.bench_function("txn_event", |b| {
+        b.iter(|| {
+            for record in &records {
+                black_box(record.deserialize::<TxnEvent>(Some(&headers)).is_ok());
+            }
+        })
+    });
+    group.finish();
+}
+
+fn add_event(c: &mut Criterion) {
+    let mut group = c.benchmark_group("add_event");
+    group.throughput(Throughput::Elements(EVENTS));
+    for (name, config) in mixes() {
+        let events = read_csv_file(feed(&config).as_slice())
+            .collect::<Result<Vec<_>, _>>()
+            .expect("valid feed");
+        group.bench_function(name, |b| {
+            b.iter_batched(
+                || (InMemoryPaymentEngine::default(), events.clone()),
+                |(mut engine, events)| {
+                    for event in events {
+                        let _ = black_box(engine.add_event(event));
+                    }
+                    engine
+                },
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+// Note: parallel parsing pays off for large feeds, sized by the default pipeline options
+fn pipeline_options(c: &mut Criterion) {
+    let feed = feed(&FeedConfig {
+        events: EVENTS,
+        ..Default::default()
+    });
+    let mut group = c.benchmark_group("pipeline_chunk_size");
+    group.throughput(Throughput::Elements(EVENTS));
+    for chunk_size in [64, 1024, 8192] {
+        let options = PipelineOptions {
+            chunk_size: chunk_size.try_into().expect("non-zero"),
+            ..Default::default()
+        };
+        group.bench_function(chunk_size.to_string(), |b| {
+            b.iter(|| {
+                let events = read_csv_parallel(std::io::Cursor::new(feed.clone()), options);
+                black_box(events.filter(Result::is_ok).count())
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, parse, deserialize, add_event, pipeline_options);
+criterion_main!(benches);
@@ -0,0 +1,118 @@
+//! Arbitrary event sequences through `InMemoryPaymentEngine`, checking invariants after every event.
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use payments_engine::{
+    decimal::PositiveDecimal,
+    payment_engine::{InMemoryPaymentEngine, PaymentEngine},
+    types::{ClientId, Timestamp, TxnEvent, TxnEventDetail, TxnId},
+};
+use rust_decimal::Decimal;
+
+/// Amount as 96 bit mantissa and scale, covering tiny, huge and out of range values, up to `Decimal::MAX`.
+#[derive(Debug, Arbitrary)]
+struct Amount {
+    lo: u32,
+    mid: u32,
+    hi: u32,
+    scale: u8,
+}
+
+impl Amount {
+    fn positive(&self) -> Option<PositiveDecimal> {
+        Decimal::from_parts(self.lo, self.mid, self.hi, false, u32::from(self.scale % 29))
+            .try_into()
+            .ok()
+    }
+}
+
+#[derive(Debug, Arbitrary)]
+enum Detail {
+    Deposit(Amount),
+    Withdrawal(Amount),
+    Dispute(Option<Amount>),
+    Resolve,
+    Chargeback(Option<Amount>),
+    Transfer(u8, Amount),
+    Refund(Option<Amount>),
+    Reversal,
+}
+
+// Note: narrow client and txn ids, so events often refer to existing accounts and transactions
+#[derive(Debug, Arbitrary)]
+struct Event {
+    client: u8,
+    txn: u8,
+    timestamp: Option<Timestamp>,
+    detail: Detail,
+}
+
+impl Event {
+    /// The event as read from a feed, `None` if an amount would be rejected by the parser.
+    fn to_txn_event(&self) -> Option<TxnEvent> {
+        let optional = |amount: &Option<Amount>| match amount {
+            Some(amount) => amount.positive().map(Some),
+            None => Some(None),
+        };
+        let detail = match &self.detail {
+            Detail::Deposit(amount) => TxnEventDetail::Deposit {
+                amount: amount.positive()?,
+            },
+            Detail::Withdrawal(amount) => TxnEventDetail::Withdrawal {
+                amount: amount.positive()?,
+            },
+            Detail::Dispute(amount) => TxnEventDetail::Dispute {
+                amount: optional(amount)?,
+            },
+            Detail::Resolve => TxnEventDetail::Resolve,
+            Detail::Chargeback(amount) => TxnEventDetail::Chargeback {
+                amount: optional(amount)?,
+            },
+            Detail::Transfer(destination, amount) => TxnEventDetail::Transfer {
+                destination_client_id: ClientId::from(destination % 8),
+                amount: amount.positive()?,
+            },
+            Detail::Refund(amount) => TxnEventDetail::Refund {
+                amount: optional(amount)?,
+            },
+            Detail::Reversal => TxnEventDetail::Reversal,
+        };
+        Some(TxnEvent {
+            client_id: ClientId::from(self.client % 8),
+            txn_id: TxnId::from(self.txn % 32),
+            timestamp: self.timestamp,
+            detail,
+        })
+    }
+}
+
+fuzz_target!(|events: Vec<Event>| {
+    let mut engine = InMemoryPaymentEngine::default();
+    for event in events.iter().filter_map(Event::to_txn_event) {
+        let before = engine.snapshots().unwrap();
+        let was_locked = engine
+            .account(event.client_id)
+            .unwrap()
+            .is_some_and(|acc| acc.locked);
+        let is_withdrawal = matches!(event.detail, TxnEventDetail::Withdrawal { .. });
+
+        let res = engine.add_event(event.clone());
+
+        if was_locked && is_withdrawal {
+            assert!(res.is_err(), "locked account withdrew: {event:?}");
+        }
+        if res.is_err() {
+            assert_eq!(before, engine.snapshots().unwrap(), "rejected {event:?}");
+        }
+
+        for snapshot in engine.snapshots().unwrap() {
+            assert_eq!(snapshot.total, snapshot.available + snapshot.held);
+        }
+        assert!(engine.audit().unwrap().is_empty(), "after {event:?}");
+        assert!(
+            engine.trial_balance().unwrap().is_balanced(),
+            "after {event:?}"
+        );
+    }
+});
@@ -0,0 +1,38 @@
+//! Arbitrary bytes through the CSV reader, then the parsed events through the engine.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use payments_engine::{
+    decimal::PositiveDecimal,
+    payment_engine::{InMemoryPaymentEngine, PaymentEngine},
+    types::TxnEventDetail,
+    util::read_csv_file,
+};
+
+fuzz_target!(|data: &[u8]| {
+    let mut engine = InMemoryPaymentEngine::default();
+    for event in read_csv_file(data).flatten() {
+        let amount = match &event.detail {
+            TxnEventDetail::Deposit { amount }
+            | TxnEventDetail::Withdrawal { amount }
+            | TxnEventDetail::Transfer { amount, .. } => Some(amount),
+            TxnEventDetail::Dispute { amount }
+            | TxnEventDetail::Chargeback { amount }
+            | TxnEventDetail::Refund { amount } => amount.as_ref(),
+            TxnEventDetail::Resolve | TxnEventDetail::Reversal => None,
+        };
+        if let Some(amount) = amount {
+            assert!(amount.is_sign_positive() && !amount.is_zero(), "{event:?}");
+            assert!(**amount <= PositiveDecimal::MAX, "{event:?}");
+            assert!(
+                amount.normalize().scale() <= PositiveDecimal::MAX_SCALE,
+                "{event:?}"
+            );
+        }
+        let _ = engine.add_event(event);
+    }
+    for snapshot in engine.snapshots().unwrap() {
+        assert_eq!(snapshot.total, snapshot.available + snapshot.held);
+    }
+    assert!(engine.trial_balance().unwrap().is_balanced());
+});
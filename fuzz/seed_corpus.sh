@@ -0,0 +1,19 @@
+#!/usr/bin/env sh
+# Seeds the read_csv corpus with the CSV feeds of the repo and its unit tests.
+set -eu
+cd "$(dirname "$0")"
+corpus=corpus/read_csv
+mkdir -p "$corpus"
+cp ../transactions.csv "$corpus/seed-transactions.csv"
+# Note: every multi-line string literal starting with a CSV header becomes a seed
+awk -v dir="$corpus" '
+    FNR == 1 { n = 0 }
+    match($0, /"type,client,tx[^"]*$/) {
+        n++; file = sprintf("%s/seed-%s-%03d.csv", dir, name, n)
+        print substr($0, RSTART + 1) > file; inside = 1; next
+    }
+    inside && /"/ {
+        sub(/".*/, ""); print > file; close(file); inside = 0; next
+    }
+    inside { print > file }
+' name=payment_engine ../src/payment_engine.rs name=types ../src/types.rs
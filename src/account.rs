@@ -1,22 +1,112 @@
 use crate::{
-    txn::Txn,
+    audit::{AccountDrift, AuditCheck},
+    error::EngineError,
+    ledger::{Ledger, LedgerAccount},
+    txn::{Txn, TxnState},
+    txn_table::TxnTable,
     types::{ClientId, TxnId},
 };
 use rust_decimal::{Decimal, RoundingStrategy};
 use serde::{Serialize, Serializer};
-use std::collections::HashMap;
+use std::{collections::VecDeque, mem::size_of};
 
 #[derive(Default, Debug)]
 pub struct Account {
-    pub txns: HashMap<TxnId, Txn>,
-    pub held_txns: HashMap<TxnId, Txn>,
+    pub txns: TxnTable,
     pub available: Decimal,
     pub held: Decimal,
     pub locked: bool,
+    /// Transactions in order of recording, for eviction, only tracked if retention is enabled
+    pub retention_order: VecDeque<TxnId>,
+    /// Net amount of evicted transactions, keeping the audit whole
+    pub evicted_net: Decimal,
+    /// Number of evicted transactions
+    pub evicted: u64,
+    /// Net amount of transactions overwritten by repeats of the same tx, keeping the audit whole
+    pub overwritten_net: Decimal,
+    /// Events of the client since the account was opened, accepted or rejected, aging open disputes
+    pub events: u64,
 }
+
+impl Account {
+    /// Checks the transaction can be recorded, repeats of the same tx overwrite only settled non-transfer transactions.
+    pub fn check_insert_txn(&self, txn_id: TxnId) -> Result<(), EngineError> {
+        match self.txns.get(&txn_id) {
+            None => Ok(()),
+            Some(existing) if existing.txn_type.is_transfer() => {
+                Err(EngineError::TransferOverwrite)
+            }
+            Some(existing) if existing.state == TxnState::Settled => Ok(()),
+            Some(existing) => Err(EngineError::TxnOverwrite(existing.state)),
+        }
+    }
+
+    /// Records the transaction, subject to `check_insert_txn()`, retiring the net amount of any overwritten one.
+    pub fn insert_txn(&mut self, txn_id: TxnId, txn: Txn) -> Result<(), EngineError> {
+        self.check_insert_txn(txn_id)?;
+        if let Some(existing) = self.txns.get(&txn_id) {
+            self.overwritten_net += existing.net_amount();
+        }
+        self.txns.insert(txn_id, txn);
+        Ok(())
+    }
+
+    /// Approximate bytes used by the account and its retained transactions.
+    pub fn memory_bytes(&self) -> usize {
+        size_of::<Account>()
+            + self.txns.memory_bytes()
+            + self.retention_order.capacity() * size_of::<TxnId>()
+    }
+
+    /// Recomputes balances from recorded transactions and the ledger, reporting any drift.
+    pub fn audit_with_ledger(&self, client_id: ClientId, ledger: &Ledger) -> Vec<AccountDrift> {
+        let ledger_available = ledger.balance(LedgerAccount::CustomerAvailable(client_id));
+        let ledger_held = ledger.balance(LedgerAccount::CustomerHeld(client_id));
+        let mut drifts = self.audit(client_id);
+        drifts.extend(
+            [
+                AccountDrift::check(
+                    client_id,
+                    AuditCheck::LedgerAvailable,
+                    ledger_available,
+                    self.available,
+                ),
+                AccountDrift::check(client_id, AuditCheck::LedgerHeld, ledger_held, self.held),
+            ]
+            .into_iter()
+            .flatten(),
+        );
+        drifts
+    }
+
+    /// Recomputes balances from recorded transactions, and the net of evicted and overwritten ones, reporting any drift.
+    pub fn audit(&self, client_id: ClientId) -> Vec<AccountDrift> {
+        let held = self.txns.values().map(|txn| txn.held_amount()).sum();
+        let total = self.evicted_net
+            + self.overwritten_net
+            + self
+                .txns
+                .values()
+                .map(|txn| txn.net_amount())
+                .sum::<Decimal>();
+        [
+            AccountDrift::check(client_id, AuditCheck::HeldTxns, held, self.held),
+            AccountDrift::check(
+                client_id,
+                AuditCheck::Total,
+                total,
+                self.available + self.held,
+            ),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+}
+
 /// AccountSnapshot summarizes an account at a given point in time.
 /// Note: available and held can be -ve in case of dispute involving withdrawals
-#[derive(Serialize, Debug, Eq, PartialEq)]
+#[derive(Serialize, Debug, Clone, Eq, PartialEq)]
 pub struct AccountSnapshot {
     #[serde(rename = "client")]
     pub client_id: ClientId,
@@ -29,7 +119,22 @@ pub struct AccountSnapshot {
     pub locked: bool,
 }
 
-fn serialize_decimal_4_places<S>(value: &Decimal, serializer: S) -> Result<S::Ok, S::Error>
+impl AccountSnapshot {
+    pub fn new(client_id: ClientId, acc: &Account) -> Self {
+        AccountSnapshot {
+            client_id,
+            available: acc.available,
+            held: acc.held,
+            total: acc.available + acc.held,
+            locked: acc.locked,
+        }
+    }
+}
+
+pub(crate) fn serialize_decimal_4_places<S>(
+    value: &Decimal,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
 {
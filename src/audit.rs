@@ -0,0 +1,46 @@
+use crate::{account::serialize_decimal_4_places, types::ClientId};
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+/// Invariants verified by the audit.
+#[derive(Serialize, Debug, Clone, Copy, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditCheck {
+    /// `held` equals the sum of disputed transaction amounts
+    HeldTxns,
+    /// `available + held` equals the net of all applied transactions
+    Total,
+    /// `available` equals the ledger's customer available balance
+    LedgerAvailable,
+    /// `held` equals the ledger's customer held balance
+    LedgerHeld,
+}
+
+/// Discrepancy between the recorded and recomputed value of an account.
+#[derive(Serialize, Debug, Eq, PartialEq)]
+pub struct AccountDrift {
+    #[serde(rename = "client")]
+    pub client_id: ClientId,
+    pub check: AuditCheck,
+    #[serde(serialize_with = "serialize_decimal_4_places")]
+    pub expected: Decimal,
+    #[serde(serialize_with = "serialize_decimal_4_places")]
+    pub actual: Decimal,
+}
+
+impl AccountDrift {
+    /// Reports drift only if expected and actual differ.
+    pub fn check(
+        client_id: ClientId,
+        check: AuditCheck,
+        expected: Decimal,
+        actual: Decimal,
+    ) -> Option<AccountDrift> {
+        (expected != actual).then_some(AccountDrift {
+            client_id,
+            check,
+            expected,
+            actual,
+        })
+    }
+}
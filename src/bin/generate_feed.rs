@@ -0,0 +1,57 @@
+use clap::Parser;
+use payments_engine::generator::{generate_feed, FeedConfig};
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+};
+
+/// Generates a synthetic transactions csv, reproducible by seed, to stdout or the output file.
+#[derive(Parser, Debug)]
+struct Args {
+    /// Number of events
+    #[arg(long, default_value_t = FeedConfig::default().events)]
+    events: u64,
+
+    /// Number of clients
+    #[arg(long, default_value_t = FeedConfig::default().clients)]
+    clients: u16,
+
+    /// Share of events disputing earlier transactions, with as many resolving or charging them back
+    #[arg(long, default_value_t = FeedConfig::default().dispute_ratio)]
+    dispute_ratio: f64,
+
+    /// Share of disputes resolved, the rest is charged back
+    #[arg(long, default_value_t = FeedConfig::default().resolve_ratio)]
+    resolve_ratio: f64,
+
+    /// Share of withdrawals among deposits and withdrawals
+    #[arg(long, default_value_t = FeedConfig::default().withdrawal_ratio)]
+    withdrawal_ratio: f64,
+
+    #[arg(long, default_value_t = FeedConfig::default().seed)]
+    seed: u64,
+
+    /// Output csv, stdout if omitted
+    #[arg(long)]
+    output: Option<PathBuf>,
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    let config = FeedConfig {
+        events: args.events,
+        clients: args.clients,
+        dispute_ratio: args.dispute_ratio,
+        resolve_ratio: args.resolve_ratio,
+        withdrawal_ratio: args.withdrawal_ratio,
+        seed: args.seed,
+    };
+    let mut out: BufWriter<Box<dyn Write>> = BufWriter::new(match &args.output {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(io::stdout().lock()),
+    });
+    generate_feed(&config, &mut out)?;
+    out.flush()?;
+    Ok(())
+}
@@ -0,0 +1,603 @@
+use crate::{
+    account::{Account, AccountSnapshot},
+    audit::AccountDrift,
+    config::EngineConfig,
+    decimal::PositiveDecimal,
+    error::{EngineError, Operation},
+    fee::FeeReportLine,
+    ledger::{Ledger, TrialBalance},
+    operations,
+    retention::MemoryReportLine,
+    txn::{TxnAction, TxnState, TxnType, TxnView},
+    types::{ClientId, Timestamp, TxnEvent, TxnEventDetail, TxnId},
+};
+use dashmap::{mapref::entry::Entry, DashMap};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Thread safe engine, shareable via `Arc`, with operations taking `&self`.
+/// Every account is guarded by its own lock, hence operations on different accounts proceed in parallel.
+/// Operations spanning 2 accounts (transfers) lock them in `ClientId` order, avoiding deadlocks.
+/// Lock order is always: account map, accounts, ledger.
+/// Note: auto resolve (`EngineConfig::auto_resolve_days`), limits (`EngineConfig::limits`) and retention
+/// (`EngineConfig::retention`) are not supported, as they require engine wide state, hence rejected by `new()`.
+/// Observers are not supported either, whilst queries and reports match `InMemoryPaymentEngine`.
+pub struct ConcurrentPaymentEngine {
+    config: EngineConfig,
+    accs: DashMap<ClientId, Arc<Mutex<Account>>>,
+    ledger: Mutex<Ledger>,
+    now: Mutex<Option<Timestamp>>,
+}
+
+impl Default for ConcurrentPaymentEngine {
+    fn default() -> Self {
+        ConcurrentPaymentEngine {
+            config: EngineConfig::default(),
+            accs: DashMap::new(),
+            ledger: Mutex::new(Ledger::default()),
+            now: Mutex::new(None),
+        }
+    }
+}
+
+impl ConcurrentPaymentEngine {
+    pub fn new(config: EngineConfig) -> anyhow::Result<Self> {
+        if config.auto_resolve_days.is_some()
+            || !config.limits.tiers.is_empty()
+            || config.retention.is_enabled()
+        {
+            anyhow::bail!(
+                "Auto resolve, limits and retention are not supported by concurrent engine"
+            )
+        }
+        Ok(ConcurrentPaymentEngine {
+            config,
+            ..Default::default()
+        })
+    }
+
+    fn account_lock(&self, client_id: ClientId) -> Option<Arc<Mutex<Account>>> {
+        self.accs.get(&client_id).map(|acc| acc.clone())
+    }
+
+    fn now(&self) -> anyhow::Result<Option<Timestamp>> {
+        Ok(*lock(&self.now)?)
+    }
+
+    /// Moves the clock to the event time, if later.
+    pub fn advance_clock(&self, timestamp: Timestamp) -> anyhow::Result<()> {
+        let mut now = lock(&self.now)?;
+        *now = Some(now.map_or(timestamp, |now| now.max(timestamp)));
+        Ok(())
+    }
+
+    /// Counts an event of the client, same as `InMemoryPaymentEngine`.
+    fn count_event(&self, client_id: ClientId) -> anyhow::Result<()> {
+        if let Some(acc_lock) = self.account_lock(client_id) {
+            lock(&acc_lock)?.events += 1;
+        }
+        Ok(())
+    }
+
+    /// Deposits into the account, allowed even if locked.
+    pub fn deposit(
+        &self,
+        client_id: ClientId,
+        txn_id: TxnId,
+        amount: PositiveDecimal,
+    ) -> anyhow::Result<()> {
+        self.count_event(client_id)?;
+        let now = self.now()?;
+        let acc_lock = self.accs.entry(client_id).or_default().clone();
+        let mut acc = lock(&acc_lock)?;
+        let entry = operations::deposit((client_id, &mut acc), txn_id, *amount, now)?;
+        lock(&self.ledger)?.post(entry)
+    }
+
+    /// Withdrawals from account, disallowed for locked account.
+    pub fn withdraw(
+        &self,
+        client_id: ClientId,
+        txn_id: TxnId,
+        amount: PositiveDecimal,
+    ) -> anyhow::Result<()> {
+        self.count_event(client_id)?;
+        let now = self.now()?;
+        let Some(acc_lock) = self.account_lock(client_id) else {
+            anyhow::bail!(EngineError::AccountNotFound(Operation::Withdraw))
+        };
+        let mut acc = lock(&acc_lock)?;
+        if acc.locked {
+            anyhow::bail!(EngineError::AccountLocked(Operation::Withdraw))
+        }
+        let entries =
+            operations::withdraw((client_id, &mut acc), txn_id, *amount, &self.config, now)?;
+        let mut ledger = lock(&self.ledger)?;
+        for entry in entries {
+            ledger.post(entry)?;
+        }
+        Ok(())
+    }
+
+    /// Transfers from the source to the destination account, same as `InMemoryPaymentEngine::transfer()`.
+    pub fn transfer(
+        &self,
+        client_id: ClientId,
+        txn_id: TxnId,
+        destination_client_id: ClientId,
+        amount: PositiveDecimal,
+    ) -> anyhow::Result<()> {
+        self.count_event(client_id)?;
+        if client_id == destination_client_id {
+            anyhow::bail!(EngineError::SelfTransfer)
+        }
+        let now = self.now()?;
+        let Some(acc_lock) = self.account_lock(client_id) else {
+            anyhow::bail!(EngineError::AccountNotFound(Operation::Transfer))
+        };
+        match self.accs.entry(destination_client_id) {
+            Entry::Occupied(entry) => {
+                let dest_lock = entry.get().clone();
+                drop(entry);
+                let (mut acc, mut dest_acc) =
+                    lock_pair((client_id, &acc_lock), (destination_client_id, &dest_lock))?;
+                self.apply_transfer(
+                    (client_id, &mut acc),
+                    (destination_client_id, &mut dest_acc),
+                    txn_id,
+                    amount,
+                    now,
+                )
+            }
+            // Note: destination is only created on success, holding on to the vacant entry meanwhile
+            Entry::Vacant(entry) => {
+                let mut acc = lock(&acc_lock)?;
+                let mut dest_acc = Account::default();
+                self.apply_transfer(
+                    (client_id, &mut acc),
+                    (destination_client_id, &mut dest_acc),
+                    txn_id,
+                    amount,
+                    now,
+                )?;
+                entry.insert(Arc::new(Mutex::new(dest_acc)));
+                Ok(())
+            }
+        }
+    }
+
+    fn apply_transfer(
+        &self,
+        (client_id, acc): (ClientId, &mut Account),
+        (destination_client_id, dest_acc): (ClientId, &mut Account),
+        txn_id: TxnId,
+        amount: PositiveDecimal,
+        now: Option<Timestamp>,
+    ) -> anyhow::Result<()> {
+        operations::check_transfer(acc, Some(dest_acc), txn_id, *amount)?;
+        let entry = operations::transfer(
+            (client_id, acc),
+            (destination_client_id, dest_acc),
+            txn_id,
+            *amount,
+            now,
+        )?;
+        lock(&self.ledger)?.post(entry)
+    }
+
+    pub fn dispute(
+        &self,
+        client_id: ClientId,
+        txn_id: TxnId,
+        amount: Option<PositiveDecimal>,
+    ) -> anyhow::Result<()> {
+        self.transition(client_id, txn_id, TxnAction::Dispute, amount)
+    }
+
+    pub fn resolve(&self, client_id: ClientId, txn_id: TxnId) -> anyhow::Result<()> {
+        self.transition(client_id, txn_id, TxnAction::Resolve, None)
+    }
+
+    pub fn chargeback(
+        &self,
+        client_id: ClientId,
+        txn_id: TxnId,
+        amount: Option<PositiveDecimal>,
+    ) -> anyhow::Result<()> {
+        self.transition(client_id, txn_id, TxnAction::Chargeback, amount)
+    }
+
+    pub fn refund(
+        &self,
+        client_id: ClientId,
+        txn_id: TxnId,
+        amount: Option<PositiveDecimal>,
+    ) -> anyhow::Result<()> {
+        self.transition(client_id, txn_id, TxnAction::Refund, amount)
+    }
+
+    pub fn reverse(&self, client_id: ClientId, txn_id: TxnId) -> anyhow::Result<()> {
+        self.transition(client_id, txn_id, TxnAction::Reverse, None)
+    }
+
+    /// Applies an action on a recorded transaction, locking both accounts of transfers.
+    fn transition(
+        &self,
+        client_id: ClientId,
+        txn_id: TxnId,
+        action: TxnAction,
+        amount: Option<PositiveDecimal>,
+    ) -> anyhow::Result<()> {
+        self.count_event(client_id)?;
+        let operation = action.operation();
+        let Some(acc_lock) = self.account_lock(client_id) else {
+            anyhow::bail!(EngineError::AccountNotFound(operation))
+        };
+        let counterparty = lock(&acc_lock)?
+            .txns
+            .get(&txn_id)
+            .and_then(|txn| match txn.txn_type {
+                TxnType::TransferOut { counterparty } => Some(counterparty),
+                _ => None,
+            });
+        match counterparty {
+            None => {
+                let mut acc = lock(&acc_lock)?;
+                self.apply_transition(vec![(client_id, &mut acc)], txn_id, action, amount)
+            }
+            Some(counterparty) => {
+                let Some(counter_lock) = self.account_lock(counterparty) else {
+                    anyhow::bail!(EngineError::TxnNotFound(operation))
+                };
+                let (mut acc, mut counter_acc) =
+                    lock_pair((client_id, &acc_lock), (counterparty, &counter_lock))?;
+                self.apply_transition(
+                    vec![(client_id, &mut acc), (counterparty, &mut counter_acc)],
+                    txn_id,
+                    action,
+                    amount,
+                )
+            }
+        }
+    }
+
+    /// Applies the action on all legs of the transaction, the 1st being the issuing client's.
+    /// Note: counterparty is only locked if the transfer was known upfront
+    fn apply_transition(
+        &self,
+        mut accs: Vec<(ClientId, &mut Account)>,
+        txn_id: TxnId,
+        action: TxnAction,
+        amount: Option<PositiveDecimal>,
+    ) -> anyhow::Result<()> {
+        let entries = operations::transition(
+            &mut accs,
+            txn_id,
+            action,
+            amount.map(|a| *a),
+            &self.config,
+            self.now()?,
+            false,
+        )?;
+        let mut ledger = lock(&self.ledger)?;
+        for entry in entries {
+            ledger.post(entry)?;
+        }
+        Ok(())
+    }
+
+    pub fn add_event(&self, event: TxnEvent) -> anyhow::Result<()> {
+        if let Some(timestamp) = event.timestamp {
+            self.advance_clock(timestamp)?;
+        }
+        match event.detail {
+            TxnEventDetail::Deposit { amount } => {
+                self.deposit(event.client_id, event.txn_id, amount)
+            }
+            TxnEventDetail::Withdrawal { amount } => {
+                self.withdraw(event.client_id, event.txn_id, amount)
+            }
+            TxnEventDetail::Dispute { amount } => {
+                self.dispute(event.client_id, event.txn_id, amount)
+            }
+            TxnEventDetail::Resolve => self.resolve(event.client_id, event.txn_id),
+            TxnEventDetail::Chargeback { amount } => {
+                self.chargeback(event.client_id, event.txn_id, amount)
+            }
+            TxnEventDetail::Transfer {
+                destination_client_id,
+                amount,
+            } => self.transfer(event.client_id, event.txn_id, destination_client_id, amount),
+            TxnEventDetail::Refund { amount } => self.refund(event.client_id, event.txn_id, amount),
+            TxnEventDetail::Reversal => self.reverse(event.client_id, event.txn_id),
+        }
+    }
+
+    pub fn account(&self, client_id: ClientId) -> anyhow::Result<Option<AccountSnapshot>> {
+        let Some(acc_lock) = self.account_lock(client_id) else {
+            return Ok(None);
+        };
+        let acc = lock(&acc_lock)?;
+        Ok(Some(AccountSnapshot::new(client_id, &acc)))
+    }
+
+    /// Snapshots of all accounts, each consistent on its own.
+    pub fn snapshots(&self) -> anyhow::Result<Vec<AccountSnapshot>> {
+        let mut snapshots = vec![];
+        for entry in self.accs.iter() {
+            let acc = lock(entry.value())?;
+            snapshots.push(AccountSnapshot::new(*entry.key(), &acc));
+        }
+        snapshots.sort_by_key(|snapshot| snapshot.client_id);
+        Ok(snapshots)
+    }
+
+    /// View of a single transaction of the client, if it exists.
+    pub fn transaction(
+        &self,
+        client_id: ClientId,
+        txn_id: TxnId,
+    ) -> anyhow::Result<Option<TxnView>> {
+        let Some(acc_lock) = self.account_lock(client_id) else {
+            return Ok(None);
+        };
+        let acc = lock(&acc_lock)?;
+        Ok(acc
+            .txns
+            .get(&txn_id)
+            .map(|txn| TxnView::new(client_id, txn_id, &txn, acc.events)))
+    }
+
+    /// Transactions of the client currently under dispute, ordered by transaction id.
+    pub fn disputed_transactions(&self, client_id: ClientId) -> anyhow::Result<Vec<TxnView>> {
+        let Some(acc_lock) = self.account_lock(client_id) else {
+            return Ok(vec![]);
+        };
+        let acc = lock(&acc_lock)?;
+        let mut views = acc
+            .txns
+            .iter()
+            .filter(|(_, txn)| txn.state == TxnState::Disputed)
+            .map(|(txn_id, txn)| TxnView::new(client_id, txn_id, &txn, acc.events))
+            .collect::<Vec<_>>();
+        views.sort_by_key(|view| view.txn_id);
+        Ok(views)
+    }
+
+    pub fn trial_balance(&self) -> anyhow::Result<TrialBalance> {
+        Ok(lock(&self.ledger)?.trial_balance())
+    }
+
+    /// Fees charged per client.
+    pub fn fee_report(&self) -> anyhow::Result<Vec<FeeReportLine>> {
+        Ok(lock(&self.ledger)?.fee_report())
+    }
+
+    /// Retained transactions and approximate memory usage per client, none being evicted without retention.
+    pub fn memory_report(&self) -> anyhow::Result<Vec<MemoryReportLine>> {
+        let mut lines = vec![];
+        for entry in self.accs.iter() {
+            let acc = lock(entry.value())?;
+            let ledger_bytes = lock(&self.ledger)?.memory_bytes(*entry.key());
+            lines.push(MemoryReportLine::new(*entry.key(), &acc, 0, ledger_bytes));
+        }
+        lines.sort_by_key(|line| line.client_id);
+        Ok(lines)
+    }
+
+    /// Audits every account against its transactions and the ledger.
+    /// Note: ledger checks are only meaningful whilst no operations are in flight.
+    pub fn audit(&self) -> anyhow::Result<Vec<AccountDrift>> {
+        let mut drifts = vec![];
+        for entry in self.accs.iter() {
+            let acc = lock(entry.value())?;
+            drifts.extend(acc.audit_with_ledger(*entry.key(), &*lock(&self.ledger)?));
+        }
+        drifts.sort_by_key(|drift| drift.client_id);
+        Ok(drifts)
+    }
+}
+
+/// Locks the mutex, failing if poisoned by a panic mid-update.
+fn lock<T>(mutex: &Mutex<T>) -> anyhow::Result<MutexGuard<'_, T>> {
+    mutex
+        .lock()
+        .map_err(|_| anyhow::anyhow!("Lock poisoned by a failed operation"))
+}
+
+/// Locks both accounts in `ClientId` order, returning the guards in argument order.
+fn lock_pair<'a>(
+    (client_id, acc): (ClientId, &'a Mutex<Account>),
+    (other_client_id, other_acc): (ClientId, &'a Mutex<Account>),
+) -> anyhow::Result<(MutexGuard<'a, Account>, MutexGuard<'a, Account>)> {
+    if client_id < other_client_id {
+        let guard = lock(acc)?;
+        Ok((guard, lock(other_acc)?))
+    } else {
+        let other_guard = lock(other_acc)?;
+        Ok((lock(acc)?, other_guard))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fee::FeeSchedule,
+        limits::{LimitsConfig, TierLimits, DEFAULT_TIER},
+        payment_engine::{InMemoryPaymentEngine, PaymentEngine},
+        retention::RetentionConfig,
+        util::{test::read_csv_contents, to_csv_string},
+    };
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+    use std::{collections::BTreeMap, thread};
+
+    const THREADS: u16 = 8;
+
+    fn amount(amount: Decimal) -> PositiveDecimal {
+        amount.try_into().unwrap()
+    }
+
+    #[test]
+    fn test_matches_in_memory() -> anyhow::Result<()> {
+        let events_csv = "type,client,tx,amount,destination
+deposit,1,101,100,
+deposit,2,201,50,
+withdrawal,1,102,30,
+transfer,1,103,20,2
+transfer,2,202,80,3
+dispute,1,101,40,
+dispute,1,103,,
+dispute,2,103,,
+resolve,1,103,,
+withdrawal,2,203,100,
+chargeback,1,101,,
+refund,1,102,10,
+reversal,2,201,,
+deposit,4,401,10,
+dispute,4,401,,";
+        let config = EngineConfig {
+            fees: FeeSchedule {
+                withdrawal_rate: dec!(0.01),
+                chargeback_fee: dec!(5),
+            },
+            ..Default::default()
+        };
+        let concurrent = ConcurrentPaymentEngine::new(config.clone())?;
+        let mut in_memory = InMemoryPaymentEngine::new(config);
+        for event in read_csv_contents(events_csv) {
+            let event = event?;
+            assert_eq!(
+                in_memory
+                    .add_event(event.clone())
+                    .map_err(|e| e.to_string()),
+                concurrent.add_event(event).map_err(|e| e.to_string())
+            );
+        }
+        assert_eq!(
+            to_csv_string(&in_memory.snapshots()?)?,
+            to_csv_string(&concurrent.snapshots()?)?
+        );
+        assert_eq!(in_memory.trial_balance()?, concurrent.trial_balance()?);
+        assert_eq!(in_memory.fee_report()?, concurrent.fee_report()?);
+        assert_eq!(in_memory.memory_report()?, concurrent.memory_report()?);
+        for client_id in 1..=4 {
+            assert_eq!(
+                in_memory.account(client_id)?,
+                concurrent.account(client_id)?
+            );
+            assert_eq!(
+                in_memory.disputed_transactions(client_id)?,
+                concurrent.disputed_transactions(client_id)?
+            );
+            for txn_id in [101, 102, 103, 201, 203, 401, 999] {
+                assert_eq!(
+                    in_memory.transaction(client_id, txn_id)?,
+                    concurrent.transaction(client_id, txn_id)?
+                );
+            }
+        }
+        assert_eq!(1, concurrent.disputed_transactions(4)?.len());
+        assert!(concurrent.audit()?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_new_rejects_unsupported_policies() {
+        let configs = [
+            EngineConfig {
+                auto_resolve_days: Some(1),
+                ..Default::default()
+            },
+            EngineConfig {
+                limits: LimitsConfig {
+                    tiers: BTreeMap::from([(DEFAULT_TIER.to_owned(), TierLimits::default())]),
+                    clients: BTreeMap::new(),
+                },
+                ..Default::default()
+            },
+            EngineConfig {
+                retention: RetentionConfig {
+                    max_txns_per_client: Some(10),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        ];
+        for config in configs {
+            assert!(ConcurrentPaymentEngine::new(config).is_err());
+        }
+        assert!(ConcurrentPaymentEngine::new(EngineConfig::default()).is_ok());
+    }
+
+    #[test]
+    fn test_no_lost_updates() -> anyhow::Result<()> {
+        let engine = Arc::new(ConcurrentPaymentEngine::default());
+        let per_thread = 500;
+        thread::scope(|scope| {
+            for t in 0..THREADS {
+                let engine = engine.clone();
+                scope.spawn(move || {
+                    for i in 0..per_thread {
+                        // all threads contend on client 0, besides their own client
+                        let txn_id = u32::from(t) * per_thread + i;
+                        engine.deposit(0, txn_id, amount(dec!(1))).unwrap();
+                        engine.deposit(t + 1, txn_id, amount(dec!(2))).unwrap();
+                        engine
+                            .withdraw(t + 1, txn_id + 1_000_000, amount(dec!(1)))
+                            .unwrap();
+                    }
+                });
+            }
+        });
+
+        let expected = Decimal::from(u32::from(THREADS) * per_thread);
+        assert_eq!(expected, engine.account(0)?.unwrap().available);
+        for client_id in 1..=THREADS {
+            assert_eq!(
+                Decimal::from(per_thread),
+                engine.account(client_id)?.unwrap().available
+            );
+        }
+        assert!(engine.audit()?.is_empty());
+        assert!(engine.trial_balance()?.is_balanced());
+        Ok(())
+    }
+
+    #[test]
+    fn test_contended_transfers() -> anyhow::Result<()> {
+        let engine = Arc::new(ConcurrentPaymentEngine::default());
+        let clients = 4;
+        for client_id in 0..clients {
+            engine.deposit(client_id, u32::from(client_id), amount(dec!(1000)))?;
+        }
+        // transfers in opposite directions, with disputes and resolves over the same accounts
+        thread::scope(|scope| {
+            for t in 0..THREADS {
+                let engine = engine.clone();
+                scope.spawn(move || {
+                    for i in 0..200 {
+                        let txn_id = 1000 + u32::from(t) * 1000 + i;
+                        let from = (t + i as u16) % clients;
+                        let to = (from + 1 + t % (clients - 1)) % clients;
+                        if engine.transfer(from, txn_id, to, amount(dec!(3))).is_ok() && i % 3 == 0
+                        {
+                            engine.dispute(from, txn_id, None).unwrap();
+                            if i % 2 == 0 {
+                                engine.resolve(from, txn_id).unwrap();
+                            }
+                        }
+                    }
+                });
+            }
+        });
+
+        let snapshots = engine.snapshots()?;
+        let total = snapshots.iter().map(|s| s.total).sum::<Decimal>();
+        assert_eq!(dec!(1000) * Decimal::from(clients), total);
+        assert!(snapshots.iter().all(|s| s.available >= Decimal::ZERO));
+        assert!(engine.audit()?.is_empty());
+        assert!(engine.trial_balance()?.is_balanced());
+        Ok(())
+    }
+}
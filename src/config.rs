@@ -0,0 +1,114 @@
+use crate::{
+    fee::FeeSchedule, limits::LimitsConfig, retention::RetentionConfig, rules::RulesConfig,
+    types::Timestamp,
+};
+use serde::Deserialize;
+use std::{fs, path::Path};
+
+/// Engine policies, defaults preserve the original behaviour.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EngineConfig {
+    /// Allows resolved transactions to be disputed again
+    pub allow_redispute: bool,
+    /// Maximum number of disputes per transaction, unlimited if `None`
+    pub max_disputes_per_txn: Option<u32>,
+    /// Disputes are rejected this many days after the original transaction, never if `None`
+    pub dispute_window_days: Option<u32>,
+    /// Disputes not charged back within this many days are resolved, never if `None`
+    pub auto_resolve_days: Option<u32>,
+    /// Fees charged on withdrawals and chargebacks
+    pub fees: FeeSchedule,
+    /// Deposit and withdrawal limits per client tier
+    pub limits: LimitsConfig,
+    /// Built-in pre-processing rules
+    pub rules: RulesConfig,
+    /// Bounds on transactions retained for disputes
+    pub retention: RetentionConfig,
+}
+
+/// Seconds per day, for converting day based policies to `Timestamp` durations
+pub const SECONDS_PER_DAY: Timestamp = 24 * 60 * 60;
+
+impl EngineConfig {
+    /// Loads the config from a JSON file if of `.json` extension, TOML otherwise.
+    pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        let config: EngineConfig = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => serde_json::from_str(&contents)?,
+            _ => toml::from_str(&contents)?,
+        };
+        config.fees.validate()?;
+        config.limits.validate()?;
+        Ok(config)
+    }
+
+    pub fn dispute_window(&self) -> Option<Timestamp> {
+        self.dispute_window_days
+            .map(|days| Timestamp::from(days) * SECONDS_PER_DAY)
+    }
+
+    pub fn auto_resolve_after(&self) -> Option<Timestamp> {
+        self.auto_resolve_days
+            .map(|days| Timestamp::from(days) * SECONDS_PER_DAY)
+    }
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        EngineConfig {
+            allow_redispute: true,
+            max_disputes_per_txn: None,
+            dispute_window_days: None,
+            auto_resolve_days: None,
+            fees: FeeSchedule::default(),
+            limits: LimitsConfig::default(),
+            rules: RulesConfig::default(),
+            retention: RetentionConfig::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_deserialize() -> anyhow::Result<()> {
+        let config: EngineConfig = toml::from_str(
+            r#"
+allow_redispute = false
+auto_resolve_days = 30
+
+[fees]
+withdrawal_rate = "0.01"
+chargeback_fee = 15
+
+[limits.tiers.default]
+max_withdrawal = 1000
+
+[limits.clients]
+1 = "default"
+"#,
+        )?;
+        assert!(!config.allow_redispute);
+        assert_eq!(None, config.max_disputes_per_txn);
+        assert_eq!(Some(30 * SECONDS_PER_DAY), config.auto_resolve_after());
+        assert_eq!(dec!(0.01), config.fees.withdrawal_rate);
+        assert_eq!(dec!(15), config.fees.chargeback_fee);
+        assert_eq!(
+            Some(dec!(1000)),
+            config.limits.tier(1).unwrap().1.max_withdrawal
+        );
+
+        let config: EngineConfig = serde_json::from_str(r#"{"fees": {"chargeback_fee": "2.5"}}"#)?;
+        assert!(config.allow_redispute);
+        assert_eq!(Decimal::ZERO, config.fees.withdrawal_rate);
+        assert_eq!(dec!(2.5), config.fees.chargeback_fee);
+
+        assert!(toml::from_str::<EngineConfig>("unknown = 1").is_err());
+        Ok(())
+    }
+}
@@ -5,9 +5,18 @@ use std::ops::Deref;
 
 /// Positive only decimal, restricted to numbers > 0,
 /// Does not expose mutable references to the inner value, to avoid opportunity to change inner to < 0
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct PositiveDecimal(Decimal);
 
+impl PositiveDecimal {
+    /// Largest accepted amount, 10^15.
+    /// Note: keeps balances far from `Decimal::MAX`, where arithmetic panics on overflow
+    pub const MAX: Decimal = Decimal::from_parts(0xA4C6_8000, 0x0003_8D7E, 0, false, 0);
+    /// Most decimal places accepted, ignoring trailing zeros.
+    /// Note: keeps balances within the 28 significant digits of `Decimal`, where sums are exact
+    pub const MAX_SCALE: u32 = 8;
+}
+
 impl<'de> Deserialize<'de> for PositiveDecimal {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -32,16 +41,24 @@ impl Deref for PositiveDecimal {
     }
 }
 
-/// Converts to PositiveDecimal if > 0
+/// Converts to PositiveDecimal if > 0, <= PositiveDecimal::MAX and of at most PositiveDecimal::MAX_SCALE places
 impl TryInto<PositiveDecimal> for Decimal {
     type Error = anyhow::Error;
 
     fn try_into(self) -> Result<PositiveDecimal, Self::Error> {
-        if self.is_sign_positive() && !self.is_zero() {
-            Ok(PositiveDecimal(self))
-        } else {
+        if !self.is_sign_positive() || self.is_zero() {
             anyhow::bail!("value must be positive and non-zero")
         }
+        if self > PositiveDecimal::MAX {
+            anyhow::bail!("value must not exceed {}", PositiveDecimal::MAX)
+        }
+        if self.normalize().scale() > PositiveDecimal::MAX_SCALE {
+            anyhow::bail!(
+                "value must have at most {} decimal places",
+                PositiveDecimal::MAX_SCALE
+            )
+        }
+        Ok(PositiveDecimal(self))
     }
 }
 
@@ -63,4 +80,25 @@ mod tests {
         let res: Result<PositiveDecimal, _> = serde_json::from_value(serde_json::json!("0"));
         assert!(res.is_err());
     }
+
+    #[test]
+    fn deserialize_positive_decimal_max() {
+        assert_eq!(PositiveDecimal::MAX, dec!(1_000_000_000_000_000));
+        let res: PositiveDecimal =
+            serde_json::from_value(serde_json::json!("1000000000000000")).unwrap();
+        assert_eq!(*res, PositiveDecimal::MAX);
+        let res: Result<PositiveDecimal, _> =
+            serde_json::from_value(serde_json::json!("1000000000000000.0001"));
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn deserialize_positive_decimal_scale() {
+        let res: PositiveDecimal =
+            serde_json::from_value(serde_json::json!("0.123456780000")).unwrap();
+        assert_eq!(res.scale(), 12);
+        let res: Result<PositiveDecimal, _> =
+            serde_json::from_value(serde_json::json!("0.123456789"));
+        assert!(res.is_err());
+    }
 }
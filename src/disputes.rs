@@ -0,0 +1,98 @@
+use crate::{
+    account::serialize_decimal_4_places,
+    ledger::serialize_display,
+    payment_engine::PaymentEngine,
+    txn::{TxnType, TxnView},
+    types::{ClientId, Timestamp, TxnId},
+};
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+/// Single transaction currently under dispute.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct OpenDispute {
+    #[serde(rename = "client")]
+    pub client_id: ClientId,
+    #[serde(rename = "tx")]
+    pub txn_id: TxnId,
+    #[serde(rename = "type", serialize_with = "serialize_display")]
+    pub txn_type: TxnType,
+    #[serde(serialize_with = "serialize_decimal_4_places")]
+    pub amount: Decimal,
+    #[serde(serialize_with = "serialize_decimal_4_places")]
+    pub disputed: Decimal,
+    pub disputed_at: Option<Timestamp>,
+    /// Seconds since the dispute was opened, as per the engine clock, if timestamps are known
+    pub age_secs: Option<Timestamp>,
+    /// Events of the client since the dispute was opened, known with or without timestamps
+    pub age_events: Option<u64>,
+}
+
+impl OpenDispute {
+    fn new(view: TxnView, now: Option<Timestamp>) -> Self {
+        OpenDispute {
+            client_id: view.client_id,
+            txn_id: view.txn_id,
+            txn_type: view.txn_type,
+            amount: view.amount,
+            disputed: view.disputed,
+            disputed_at: view.disputed_at,
+            age_secs: now
+                .zip(view.disputed_at)
+                .map(|(now, disputed_at)| now.saturating_sub(disputed_at)),
+            age_events: view.dispute_age_events,
+        }
+    }
+}
+
+/// Lists every transaction currently under dispute across all clients, ordered by client and transaction.
+/// Note: disputed transfers are listed for both the source and the destination.
+pub fn open_disputes_report(engine: &dyn PaymentEngine) -> anyhow::Result<Vec<OpenDispute>> {
+    let now = engine.now();
+    let mut disputes = vec![];
+    for snapshot in engine.snapshots()? {
+        disputes.extend(
+            engine
+                .disputed_transactions(snapshot.client_id)?
+                .into_iter()
+                .map(|view| OpenDispute::new(view, now)),
+        );
+    }
+    Ok(disputes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        payment_engine::InMemoryPaymentEngine,
+        util::{test::add_csv_events_to_engine, to_csv_string},
+    };
+
+    #[test]
+    fn test_open_disputes_report() -> anyhow::Result<()> {
+        let mut engine = InMemoryPaymentEngine::default();
+        let events_csv = "type,client,tx,amount,timestamp
+deposit,2,201,100,0
+deposit,1,101,50,10
+withdrawal,1,102,20,20
+dispute,2,201,30,30
+dispute,1,102,,40
+dispute,1,101,,50
+resolve,1,101,,60
+deposit,3,301,10,100";
+        add_csv_events_to_engine(&mut engine, events_csv)?;
+
+        assert_eq!(
+            to_csv_string(&open_disputes_report(&engine)?)?,
+            "client,tx,type,amount,disputed,disputed_at,age_secs,age_events
+1,102,withdrawal,20,20,40,60,2
+2,201,deposit,100,30,30,70,0"
+        );
+        assert_eq!(
+            serde_json::to_string(&open_disputes_report(&engine)?[0])?,
+            r#"{"client":1,"tx":102,"type":"withdrawal","amount":"20","disputed":"20","disputed_at":40,"age_secs":60,"age_events":2}"#
+        );
+        Ok(())
+    }
+}
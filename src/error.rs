@@ -0,0 +1,107 @@
+use crate::{
+    limits::LimitKind,
+    txn::{TxnAction, TxnState},
+};
+use rust_decimal::Decimal;
+use std::fmt;
+
+/// Engine operations, as referenced by errors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Deposit,
+    Withdraw,
+    Dispute,
+    Resolve,
+    Chargeback,
+    Transfer,
+    Refund,
+    Reverse,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Operation::Deposit => "deposit",
+            Operation::Withdraw => "withdraw",
+            Operation::Dispute => "dispute",
+            Operation::Resolve => "resolve",
+            Operation::Chargeback => "chargeback",
+            Operation::Transfer => "transfer",
+            Operation::Refund => "refund",
+            Operation::Reverse => "reverse",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Reasons for rejecting an operation, retrievable via `anyhow::Error::downcast_ref()`.
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum EngineError {
+    #[error("Cannot {0} non-existent account")]
+    AccountNotFound(Operation),
+    #[error("Cannot {0} locked account")]
+    AccountLocked(Operation),
+    #[error("Cannot {0} non-existent transaction")]
+    TxnNotFound(Operation),
+    #[error("Cannot {0} due to insufficient funds")]
+    InsufficientFunds(Operation),
+    #[error("Cannot refund non-withdrawal transaction")]
+    RefundNonWithdrawal,
+    #[error("Cannot transfer to the same account")]
+    SelfTransfer,
+    #[error("Cannot overwrite transfer transaction")]
+    TransferOverwrite,
+    #[error("Cannot {0} incoming transfer, only its source can")]
+    IncomingTransfer(Operation),
+    #[error("Cannot {0} transaction no longer retained")]
+    TxnEvicted(Operation),
+    #[error("Cannot overwrite transaction in {0:?} state")]
+    TxnOverwrite(TxnState),
+    #[error("Cannot {action} transaction in {state:?} state")]
+    InvalidTransition { state: TxnState, action: TxnAction },
+    #[error("Cannot re-dispute resolved transaction")]
+    RedisputeNotAllowed,
+    #[error("Cannot dispute transaction more than {0} time(s)")]
+    DisputeLimitExceeded(u32),
+    #[error("Cannot dispute transaction older than {0} day(s)")]
+    DisputeWindowExpired(u32),
+    #[error("Cannot {action} {requested}, exceeding remaining {limit}")]
+    AmountExceeded {
+        action: TxnAction,
+        requested: Decimal,
+        limit: Decimal,
+    },
+    #[error("Cannot {operation}, exceeding {kind} limit of {limit}")]
+    LimitExceeded {
+        operation: Operation,
+        kind: LimitKind,
+        limit: Decimal,
+    },
+    #[error("Rule {rule} rejected event: {reason}")]
+    RuleRejected { rule: String, reason: String },
+}
+
+impl EngineError {
+    /// Stable name of the rejection reason, eg. for aggregating in reports.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            EngineError::AccountNotFound(_) => "account_not_found",
+            EngineError::AccountLocked(_) => "account_locked",
+            EngineError::TxnNotFound(_) => "txn_not_found",
+            EngineError::InsufficientFunds(_) => "insufficient_funds",
+            EngineError::RefundNonWithdrawal => "refund_non_withdrawal",
+            EngineError::SelfTransfer => "self_transfer",
+            EngineError::TransferOverwrite => "transfer_overwrite",
+            EngineError::IncomingTransfer(_) => "incoming_transfer",
+            EngineError::TxnEvicted(_) => "txn_evicted",
+            EngineError::TxnOverwrite(_) => "txn_overwrite",
+            EngineError::InvalidTransition { .. } => "invalid_transition",
+            EngineError::RedisputeNotAllowed => "redispute_not_allowed",
+            EngineError::DisputeLimitExceeded(_) => "dispute_limit_exceeded",
+            EngineError::DisputeWindowExpired(_) => "dispute_window_expired",
+            EngineError::AmountExceeded { .. } => "amount_exceeded",
+            EngineError::LimitExceeded { .. } => "limit_exceeded",
+            EngineError::RuleRejected { .. } => "rule_rejected",
+        }
+    }
+}
@@ -0,0 +1,188 @@
+use crate::{
+    account::serialize_decimal_4_places,
+    decimal::PositiveDecimal,
+    ledger::{JournalEntry, JournalEntryKind, LedgerAccount},
+    types::ClientId,
+};
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Fees charged to clients, credited to the house account. No fees are charged by default.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FeeSchedule {
+    /// Fraction of the withdrawn amount charged on top of each withdrawal, eg. 0.01 for 1%
+    pub withdrawal_rate: Decimal,
+    /// Fixed fee charged per chargeback
+    pub chargeback_fee: Decimal,
+}
+
+impl FeeSchedule {
+    /// Withdrawal fee, rounded to 4 decimal places.
+    pub fn withdrawal_fee(&self, amount: Decimal) -> Decimal {
+        (amount * self.withdrawal_rate)
+            .round_dp_with_strategy(4, RoundingStrategy::MidpointAwayFromZero)
+            .normalize()
+    }
+
+    /// Note: fees are bounded as transaction amounts, see `PositiveDecimal::MAX`, keeping balances exact and in range
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.withdrawal_rate.is_sign_negative() || self.chargeback_fee.is_sign_negative() {
+            anyhow::bail!("Fees cannot be negative")
+        }
+        if self.withdrawal_rate > Decimal::ONE {
+            anyhow::bail!("Withdrawal rate cannot exceed 1")
+        }
+        if self.chargeback_fee > PositiveDecimal::MAX
+            || self.chargeback_fee.normalize().scale() > PositiveDecimal::MAX_SCALE
+        {
+            anyhow::bail!(
+                "Chargeback fee cannot exceed {} or {} decimal places",
+                PositiveDecimal::MAX,
+                PositiveDecimal::MAX_SCALE
+            )
+        }
+        Ok(())
+    }
+}
+
+/// Fees charged to a single client, by kind.
+#[derive(Serialize, Debug, Default, Clone, Eq, PartialEq)]
+pub struct FeeReportLine {
+    #[serde(rename = "client")]
+    pub client_id: ClientId,
+    #[serde(serialize_with = "serialize_decimal_4_places")]
+    pub withdrawal: Decimal,
+    #[serde(serialize_with = "serialize_decimal_4_places")]
+    pub chargeback: Decimal,
+    #[serde(serialize_with = "serialize_decimal_4_places")]
+    pub total: Decimal,
+}
+
+/// Running totals of fees per client, fed with every journal entry, rather than keeping the journal.
+#[derive(Debug, Default)]
+pub struct FeeTotals {
+    lines: BTreeMap<ClientId, FeeReportLine>,
+}
+
+impl FeeTotals {
+    /// Adds up the fee, if a fee entry.
+    pub fn record(&mut self, entry: &JournalEntry) {
+        if !matches!(
+            entry.kind,
+            JournalEntryKind::WithdrawalFee | JournalEntryKind::ChargebackFee
+        ) {
+            return;
+        }
+        let fee = entry
+            .postings
+            .iter()
+            .filter(|p| p.account == LedgerAccount::House)
+            .map(|p| p.amount)
+            .sum::<Decimal>();
+        let line = self
+            .lines
+            .entry(entry.client_id)
+            .or_insert_with(|| FeeReportLine {
+                client_id: entry.client_id,
+                ..Default::default()
+            });
+        if entry.kind == JournalEntryKind::WithdrawalFee {
+            line.withdrawal += fee;
+        } else {
+            line.chargeback += fee;
+        }
+        line.total += fee;
+    }
+
+    /// Fees per client, ordered by client.
+    pub fn report(&self) -> Vec<FeeReportLine> {
+        self.lines.values().cloned().collect()
+    }
+
+    pub fn contains(&self, client_id: ClientId) -> bool {
+        self.lines.contains_key(&client_id)
+    }
+
+    /// Takes the client's totals out, eg. to move them to another ledger.
+    pub fn take(&mut self, client_id: ClientId) -> Option<FeeReportLine> {
+        self.lines.remove(&client_id)
+    }
+
+    pub fn put(&mut self, line: FeeReportLine) {
+        self.lines.insert(line.client_id, line);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_withdrawal_fee() {
+        let fees = FeeSchedule {
+            withdrawal_rate: dec!(0.015),
+            chargeback_fee: dec!(15),
+        };
+        assert_eq!(dec!(1.5), fees.withdrawal_fee(dec!(100)));
+        assert_eq!(dec!(0.0002), fees.withdrawal_fee(dec!(0.015)));
+        assert_eq!(
+            Decimal::ZERO,
+            FeeSchedule::default().withdrawal_fee(dec!(100))
+        );
+        assert!(fees.validate().is_ok());
+
+        for fees in [
+            FeeSchedule {
+                chargeback_fee: dec!(-1),
+                ..Default::default()
+            },
+            FeeSchedule {
+                withdrawal_rate: dec!(1.01),
+                ..Default::default()
+            },
+            FeeSchedule {
+                chargeback_fee: PositiveDecimal::MAX + dec!(1),
+                ..Default::default()
+            },
+            FeeSchedule {
+                chargeback_fee: dec!(0.123456789),
+                ..Default::default()
+            },
+        ] {
+            assert!(fees.validate().is_err());
+        }
+    }
+
+    #[test]
+    fn test_fee_totals() {
+        let mut totals = FeeTotals::default();
+        for (kind, amount) in [
+            (JournalEntryKind::WithdrawalFee, dec!(1.5)),
+            (JournalEntryKind::ChargebackFee, dec!(15)),
+            (JournalEntryKind::Withdrawal, dec!(100)),
+            (JournalEntryKind::WithdrawalFee, dec!(0.5)),
+        ] {
+            totals.record(&JournalEntry::transfer(
+                kind,
+                1,
+                101,
+                LedgerAccount::CustomerAvailable(1),
+                LedgerAccount::House,
+                amount,
+            ));
+        }
+        assert!(totals.contains(1) && !totals.contains(2));
+        assert_eq!(
+            vec![FeeReportLine {
+                client_id: 1,
+                withdrawal: dec!(2),
+                chargeback: dec!(15),
+                total: dec!(17),
+            }],
+            totals.report()
+        );
+    }
+}
@@ -0,0 +1,142 @@
+use crate::types::{ClientId, TxnId};
+use std::io::{self, Write};
+
+/// Shape of a synthetic feed.
+#[derive(Debug, Clone, Copy)]
+pub struct FeedConfig {
+    /// Number of events, excluding the header
+    pub events: u64,
+    pub clients: ClientId,
+    /// Share of events disputing an earlier transaction, followed by as many resolves and chargebacks
+    pub dispute_ratio: f64,
+    /// Share of resolved disputes, the rest is charged back
+    pub resolve_ratio: f64,
+    /// Share of withdrawals among deposits and withdrawals
+    pub withdrawal_ratio: f64,
+    pub seed: u64,
+}
+
+impl Default for FeedConfig {
+    fn default() -> Self {
+        FeedConfig {
+            events: 1_000_000,
+            clients: 1000,
+            dispute_ratio: 0.01,
+            resolve_ratio: 0.8,
+            withdrawal_ratio: 0.3,
+            seed: 0,
+        }
+    }
+}
+
+/// SplitMix64, keeping feeds reproducible by seed regardless of dependency versions.
+#[derive(Debug)]
+struct Rng(u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `0..bound`.
+    fn below(&mut self, bound: u64) -> u64 {
+        self.next_u64() % bound
+    }
+
+    /// Uniform in `[0, 1)`.
+    fn ratio(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1_u64 << 53) as f64
+    }
+}
+
+/// Writes a synthetic feed of deposits, withdrawals, disputes, resolves and chargebacks as CSV.
+/// Disputes pick earlier transactions of the client at random, and are later resolved or charged back.
+pub fn generate_feed(config: &FeedConfig, out: &mut impl Write) -> io::Result<()> {
+    let mut rng = Rng(config.seed);
+    let clients = u64::from(config.clients.max(1));
+    // Note: recent transactions per client, as candidates for disputes, bounded to keep memory flat
+    let mut recent: Vec<Vec<TxnId>> = vec![vec![]; clients as usize];
+    let mut open_disputes: Vec<(ClientId, TxnId)> = vec![];
+    let mut next_txn_id: TxnId = 1;
+
+    writeln!(out, "type,client,tx,amount")?;
+    for _ in 0..config.events {
+        let client_id = rng.below(clients) as ClientId;
+        let roll = rng.ratio();
+        if roll < config.dispute_ratio && !open_disputes.is_empty() {
+            let (client_id, txn_id) =
+                open_disputes.swap_remove(rng.below(open_disputes.len() as u64) as usize);
+            let action = if rng.ratio() < config.resolve_ratio {
+                "resolve"
+            } else {
+                "chargeback"
+            };
+            writeln!(out, "{action},{client_id},{txn_id},")?;
+            continue;
+        }
+        let candidates = &mut recent[client_id as usize];
+        if roll < config.dispute_ratio * 2.0 && !candidates.is_empty() {
+            let txn_id = candidates.swap_remove(rng.below(candidates.len() as u64) as usize);
+            open_disputes.push((client_id, txn_id));
+            writeln!(out, "dispute,{client_id},{txn_id},")?;
+            continue;
+        }
+        let txn_id = next_txn_id;
+        next_txn_id = next_txn_id.wrapping_add(1);
+        // amounts up to 10k, of up to 4 decimal places
+        let amount = rng.below(100_000_000) + 1;
+        let (units, fraction) = (amount / 10_000, amount % 10_000);
+        let txn_type = if rng.ratio() < config.withdrawal_ratio {
+            "withdrawal"
+        } else {
+            "deposit"
+        };
+        writeln!(out, "{txn_type},{client_id},{txn_id},{units}.{fraction:04}")?;
+        if candidates.len() == 64 {
+            candidates.swap_remove(rng.below(64) as usize);
+        }
+        candidates.push(txn_id);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::test::read_csv_contents;
+
+    fn feed(config: &FeedConfig) -> String {
+        let mut out = vec![];
+        generate_feed(config, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_generate_feed() {
+        let config = FeedConfig {
+            events: 10_000,
+            clients: 10,
+            dispute_ratio: 0.05,
+            seed: 42,
+            ..Default::default()
+        };
+        let contents = feed(&config);
+        assert_eq!(contents, feed(&config));
+        assert_ne!(contents, feed(&FeedConfig { seed: 43, ..config }));
+
+        let events = read_csv_contents(&contents)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(10_000, events.len());
+        assert!(events.iter().all(|event| event.client_id < 10));
+        let disputes = contents
+            .lines()
+            .filter(|l| l.starts_with("dispute"))
+            .count();
+        assert!((300..700).contains(&disputes), "{disputes} disputes");
+    }
+}
@@ -0,0 +1,293 @@
+use crate::{
+    error::EngineError,
+    ledger::LedgerAccount,
+    payment_engine::PaymentEngine,
+    rules::{RuleChain, RuleFlag},
+    types::{TxnEvent, TxnEventDetail},
+};
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::{collections::BTreeMap, time::Instant};
+use tracing::warn;
+
+/// Reason of events that failed to parse.
+pub const PARSE_ERROR: &str = "parse_error";
+/// Reason of rejections other than `EngineError`s.
+pub const OTHER_ERROR: &str = "other";
+/// Flags listed by the summary, beyond which flags are only counted, bounding memory on large feeds.
+pub const MAX_LISTED_FLAGS: usize = 10_000;
+
+/// Statistics of a processed batch of events.
+#[derive(Serialize, Debug, Default, Clone, PartialEq)]
+pub struct BatchSummary {
+    /// Parsed events per type
+    pub events_by_type: BTreeMap<&'static str, u64>,
+    pub accepted: u64,
+    pub rejected: u64,
+    /// Rejected events per `EngineError::reason()`, or `parse_error`
+    pub rejected_by_reason: BTreeMap<&'static str, u64>,
+    /// Amount of accepted deposits
+    pub deposited: Decimal,
+    /// Amount of accepted withdrawals
+    pub withdrawn: Decimal,
+    /// Held across all accounts at the end of the batch
+    pub held: Decimal,
+    /// Net amount charged back at the end of the batch
+    pub charged_back: Decimal,
+    /// Events flagged by rules for review, processed nonetheless
+    pub flagged: u64,
+    /// Flags raised per rule
+    pub flagged_by_rule: BTreeMap<String, u64>,
+    /// First `MAX_LISTED_FLAGS` flags raised by rules, as reported by `--flag-report`
+    #[serde(skip)]
+    pub flags: Vec<RuleFlag>,
+    pub accounts: u64,
+    pub locked_accounts: u64,
+    pub elapsed_secs: f64,
+    /// Throughput of the batch
+    pub events_per_sec: f64,
+}
+
+impl BatchSummary {
+    /// Records the outcome of an event.
+    pub fn record(&mut self, detail: &TxnEventDetail, res: &anyhow::Result<()>) {
+        *self.events_by_type.entry(detail.name()).or_default() += 1;
+        match res {
+            Ok(()) => {
+                self.accepted += 1;
+                match detail {
+                    TxnEventDetail::Deposit { amount } => self.deposited += **amount,
+                    TxnEventDetail::Withdrawal { amount } => self.withdrawn += **amount,
+                    _ => (),
+                }
+            }
+            Err(err) => {
+                let reason = err
+                    .downcast_ref::<EngineError>()
+                    .map_or(OTHER_ERROR, EngineError::reason);
+                self.record_rejection(reason);
+            }
+        }
+    }
+
+    pub fn record_parse_error(&mut self) {
+        self.record_rejection(PARSE_ERROR);
+    }
+
+    /// Records flags raised by rules.
+    pub fn record_flags(&mut self, flags: Vec<RuleFlag>) {
+        self.flagged += flags.len() as u64;
+        for flag in &flags {
+            *self.flagged_by_rule.entry(flag.rule.clone()).or_default() += 1;
+        }
+        self.list_flags(flags);
+    }
+
+    fn list_flags(&mut self, flags: Vec<RuleFlag>) {
+        let room = MAX_LISTED_FLAGS.saturating_sub(self.flags.len());
+        self.flags.extend(flags.into_iter().take(room));
+    }
+
+    fn record_rejection(&mut self, reason: &'static str) {
+        self.rejected += 1;
+        *self.rejected_by_reason.entry(reason).or_default() += 1;
+    }
+
+    /// Captures the end state of the engine.
+    pub fn finish(&mut self, engine: &dyn PaymentEngine) -> anyhow::Result<()> {
+        let snapshots = engine.snapshots()?;
+        self.accounts = snapshots.len() as u64;
+        self.locked_accounts = snapshots.iter().filter(|s| s.locked).count() as u64;
+        self.held = snapshots.iter().map(|s| s.held).sum();
+        self.charged_back = engine
+            .trial_balance()?
+            .lines
+            .iter()
+            .filter(|line| line.account == LedgerAccount::ChargebackLoss)
+            .map(|line| line.credit - line.debit)
+            .sum();
+        Ok(())
+    }
+
+    /// Combines summaries of batches processed separately, eg. by different engines.
+    pub fn merge(&mut self, other: BatchSummary) {
+        for (event_type, count) in other.events_by_type {
+            *self.events_by_type.entry(event_type).or_default() += count;
+        }
+        for (reason, count) in other.rejected_by_reason {
+            *self.rejected_by_reason.entry(reason).or_default() += count;
+        }
+        self.accepted += other.accepted;
+        self.rejected += other.rejected;
+        self.deposited += other.deposited;
+        self.withdrawn += other.withdrawn;
+        self.held += other.held;
+        self.charged_back += other.charged_back;
+        self.flagged += other.flagged;
+        for (rule, count) in other.flagged_by_rule {
+            *self.flagged_by_rule.entry(rule).or_default() += count;
+        }
+        self.list_flags(other.flags);
+        self.accounts += other.accounts;
+        self.locked_accounts += other.locked_accounts;
+        // Note: batches are assumed to be processed in parallel
+        self.elapsed_secs = self.elapsed_secs.max(other.elapsed_secs);
+        self.update_throughput();
+    }
+
+    pub fn events(&self) -> u64 {
+        self.accepted + self.rejected
+    }
+
+    pub(crate) fn update_throughput(&mut self) {
+        self.events_per_sec = if self.elapsed_secs > 0.0 {
+            self.events() as f64 / self.elapsed_secs
+        } else {
+            0.0
+        };
+    }
+}
+
+/// Feeds the events through the rules into the engine, logging and skipping errors.
+pub fn ingest(
+    events: impl Iterator<Item = csv::Result<TxnEvent>>,
+    rules: &mut RuleChain,
+    engine: &mut dyn PaymentEngine,
+) -> anyhow::Result<BatchSummary> {
+    let start = Instant::now();
+    let mut summary = BatchSummary::default();
+    ingest_into(events, rules, engine, &mut summary);
+    summary.finish(engine)?;
+    summary.elapsed_secs = start.elapsed().as_secs_f64();
+    summary.update_throughput();
+    Ok(summary)
+}
+
+/// As `ingest()`, recording outcomes into the summary, left to be finished by the caller.
+pub fn ingest_into(
+    events: impl Iterator<Item = csv::Result<TxnEvent>>,
+    rules: &mut RuleChain,
+    engine: &mut dyn PaymentEngine,
+    summary: &mut BatchSummary,
+) {
+    for event in events {
+        match event {
+            Ok(event) => {
+                // Note: event is consumed by the engine
+                let detail = event.detail.clone();
+                let res = rules.add_event(engine, event);
+                if let Err(err) = &res {
+                    warn!(?err, "Error processing event") // Note: skipping errors
+                }
+                summary.record(&detail, &res);
+                summary.record_flags(rules.take_flags());
+            }
+            Err(err) => {
+                warn!(?err, "Error reading event"); // Note: skipping errors
+                summary.record_parse_error();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        payment_engine::InMemoryPaymentEngine,
+        rules::{AmountThresholds, RulesConfig},
+        util::test::read_csv_contents,
+    };
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_ingest() -> anyhow::Result<()> {
+        let mut engine = InMemoryPaymentEngine::default();
+        let events_csv = "type,client,tx,amount
+deposit,1,101,100
+deposit,2,201,50
+withdrawal,1,102,30
+withdrawal,2,202,60
+dispute,1,101,
+dispute,1,103,
+dispute,2,201,
+chargeback,2,201,
+deposit,1,104,x";
+        let mut rules = RuleChain::from_config(&RulesConfig {
+            amount_thresholds: Some(AmountThresholds {
+                flag_above: Some(dec!(75)),
+                reject_above: None,
+            }),
+            ..Default::default()
+        });
+        let summary = ingest(read_csv_contents(events_csv), &mut rules, &mut engine)?;
+
+        assert_eq!(
+            BTreeMap::from([
+                ("chargeback", 1),
+                ("deposit", 2),
+                ("dispute", 3),
+                ("withdrawal", 2)
+            ]),
+            summary.events_by_type
+        );
+        assert_eq!(6, summary.accepted);
+        assert_eq!(3, summary.rejected);
+        assert_eq!(9, summary.events());
+        assert_eq!(
+            BTreeMap::from([
+                ("insufficient_funds", 1),
+                ("parse_error", 1),
+                ("txn_not_found", 1)
+            ]),
+            summary.rejected_by_reason
+        );
+        assert_eq!(dec!(150), summary.deposited);
+        assert_eq!(dec!(30), summary.withdrawn);
+        assert_eq!(dec!(100), summary.held);
+        assert_eq!(dec!(50), summary.charged_back);
+        assert_eq!((2, 1), (summary.accounts, summary.locked_accounts));
+        assert_eq!(1, summary.flagged);
+        assert_eq!(
+            (1, 101),
+            (summary.flags[0].client_id, summary.flags[0].txn_id)
+        );
+        assert_eq!(
+            BTreeMap::from([("amount_thresholds".to_owned(), 1)]),
+            summary.flagged_by_rule
+        );
+        assert!(rules.flags().is_empty());
+
+        let mut merged = summary.clone();
+        merged.merge(summary);
+        assert_eq!(12, merged.accepted);
+        assert_eq!(2, merged.rejected_by_reason["parse_error"]);
+        assert_eq!(dec!(300), merged.deposited);
+        assert_eq!(4, merged.accounts);
+        assert_eq!((2, 2), (merged.flagged, merged.flags.len() as u64));
+        assert_eq!(2, merged.flagged_by_rule["amount_thresholds"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_flags_capped() {
+        let flag = RuleFlag {
+            client_id: 1,
+            txn_id: 101,
+            rule: "amount_thresholds".to_owned(),
+            reason: "amount 100 above 75".to_owned(),
+        };
+        let mut summary = BatchSummary::default();
+        summary.record_flags(vec![flag.clone(); MAX_LISTED_FLAGS - 1]);
+        summary.record_flags(vec![flag.clone(); 2]);
+        let mut merged = summary.clone();
+        merged.merge(summary);
+
+        assert_eq!(2 * MAX_LISTED_FLAGS as u64 + 2, merged.flagged);
+        assert_eq!(
+            2 * MAX_LISTED_FLAGS as u64 + 2,
+            merged.flagged_by_rule["amount_thresholds"]
+        );
+        assert_eq!(MAX_LISTED_FLAGS, merged.flags.len());
+    }
+}
@@ -0,0 +1,366 @@
+use crate::{
+    account::serialize_decimal_4_places,
+    fee::{FeeReportLine, FeeTotals},
+    types::{ClientId, TxnId},
+};
+use rust_decimal::Decimal;
+use serde::{Serialize, Serializer};
+use std::{cmp::Ordering, collections::BTreeMap, fmt, mem::size_of};
+
+/// Accounts maintained by the ledger, per client and house wide.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LedgerAccount {
+    /// Funds available to the client
+    CustomerAvailable(ClientId),
+    /// Funds of the client held due to disputes
+    CustomerHeld(ClientId),
+    /// Counterpart of funds entering and leaving the engine
+    Settlement,
+    /// Funds returned to the issuer on chargebacks
+    ChargebackLoss,
+    /// Fees collected from clients
+    House,
+}
+
+impl LedgerAccount {
+    /// Orders customer accounts by client, followed by house accounts
+    fn sort_key(&self) -> (u8, ClientId, u8) {
+        match *self {
+            LedgerAccount::CustomerAvailable(client_id) => (0, client_id, 0),
+            LedgerAccount::CustomerHeld(client_id) => (0, client_id, 1),
+            LedgerAccount::Settlement => (1, 0, 0),
+            LedgerAccount::ChargebackLoss => (2, 0, 0),
+            LedgerAccount::House => (3, 0, 0),
+        }
+    }
+}
+
+impl Ord for LedgerAccount {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.sort_key().cmp(&other.sort_key())
+    }
+}
+
+impl PartialOrd for LedgerAccount {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for LedgerAccount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LedgerAccount::CustomerAvailable(client_id) => {
+                write!(f, "client:{client_id}:available")
+            }
+            LedgerAccount::CustomerHeld(client_id) => write!(f, "client:{client_id}:held"),
+            LedgerAccount::Settlement => write!(f, "settlement"),
+            LedgerAccount::ChargebackLoss => write!(f, "chargeback_loss"),
+            LedgerAccount::House => write!(f, "house"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JournalEntryKind {
+    Deposit,
+    Withdrawal,
+    Dispute,
+    Resolve,
+    Chargeback,
+    Transfer,
+    Refund,
+    Reversal,
+    WithdrawalFee,
+    ChargebackFee,
+}
+
+/// Signed movement on a single ledger account, credits are +ve, debits are -ve.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Posting {
+    pub account: LedgerAccount,
+    pub amount: Decimal,
+}
+
+/// Set of postings representing a single operation, postings must sum up to zero.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JournalEntry {
+    pub kind: JournalEntryKind,
+    pub client_id: ClientId,
+    pub txn_id: TxnId,
+    pub postings: Vec<Posting>,
+}
+
+impl JournalEntry {
+    /// 2 legged entry, debiting `from` and crediting `to`.
+    pub fn transfer(
+        kind: JournalEntryKind,
+        client_id: ClientId,
+        txn_id: TxnId,
+        from: LedgerAccount,
+        to: LedgerAccount,
+        amount: Decimal,
+    ) -> Self {
+        JournalEntry {
+            kind,
+            client_id,
+            txn_id,
+            postings: vec![
+                Posting {
+                    account: from,
+                    amount: -amount,
+                },
+                Posting {
+                    account: to,
+                    amount,
+                },
+            ],
+        }
+    }
+
+    pub fn is_balanced(&self) -> bool {
+        self.postings
+            .iter()
+            .map(|p| p.amount)
+            .sum::<Decimal>()
+            .is_zero()
+    }
+}
+
+/// Double-entry ledger, recording every operation as a balanced journal entry.
+/// Note: entries are not kept, only account balances and fee totals, bounding memory by the number of clients
+#[derive(Default, Debug)]
+pub struct Ledger {
+    balances: BTreeMap<LedgerAccount, Decimal>,
+    fees: FeeTotals,
+}
+
+impl Ledger {
+    /// Records the entry, rejecting it if postings do not balance.
+    pub fn post(&mut self, entry: JournalEntry) -> anyhow::Result<()> {
+        if !entry.is_balanced() {
+            anyhow::bail!("Cannot post unbalanced journal entry")
+        }
+        self.record(entry);
+        Ok(())
+    }
+
+    /// Posts a 2 legged entry, debiting `from` and crediting `to`.
+    pub fn transfer(
+        &mut self,
+        kind: JournalEntryKind,
+        client_id: ClientId,
+        txn_id: TxnId,
+        from: LedgerAccount,
+        to: LedgerAccount,
+        amount: Decimal,
+    ) {
+        // Note: 2 legs of the same amount always balance
+        self.record(JournalEntry::transfer(
+            kind, client_id, txn_id, from, to, amount,
+        ));
+    }
+
+    fn record(&mut self, entry: JournalEntry) {
+        for posting in &entry.postings {
+            *self.balances.entry(posting.account).or_default() += posting.amount;
+        }
+        self.fees.record(&entry);
+    }
+
+    pub fn balance(&self, account: LedgerAccount) -> Decimal {
+        self.balances.get(&account).copied().unwrap_or_default()
+    }
+
+    pub fn fee_report(&self) -> Vec<FeeReportLine> {
+        self.fees.report()
+    }
+
+    /// Approximate bytes of the client's balances and fee totals.
+    pub fn memory_bytes(&self, client_id: ClientId) -> usize {
+        let balances = [
+            LedgerAccount::CustomerAvailable(client_id),
+            LedgerAccount::CustomerHeld(client_id),
+        ]
+        .iter()
+        .filter(|account| self.balances.contains_key(account))
+        .count();
+        balances * size_of::<(LedgerAccount, Decimal)>()
+            + usize::from(self.fees.contains(client_id)) * size_of::<(ClientId, FeeReportLine)>()
+    }
+
+    pub fn trial_balance(&self) -> TrialBalance {
+        TrialBalance::from_balances(self.balances.iter().map(|(&acc, &bal)| (acc, bal)))
+    }
+
+    /// Takes the client's balances and fee totals out, eg. to move them to another ledger.
+    /// Note: either ledger no longer balances on its own, only combined with the other
+    pub fn take_client(&mut self, client_id: ClientId) -> ClientLedger {
+        ClientLedger {
+            available: self
+                .balances
+                .remove(&LedgerAccount::CustomerAvailable(client_id)),
+            held: self
+                .balances
+                .remove(&LedgerAccount::CustomerHeld(client_id)),
+            fees: self.fees.take(client_id),
+        }
+    }
+
+    /// Moves in the client's balances and fee totals, as taken from another ledger.
+    pub fn put_client(&mut self, client_id: ClientId, client_ledger: ClientLedger) {
+        if let Some(available) = client_ledger.available {
+            self.balances
+                .insert(LedgerAccount::CustomerAvailable(client_id), available);
+        }
+        if let Some(held) = client_ledger.held {
+            self.balances
+                .insert(LedgerAccount::CustomerHeld(client_id), held);
+        }
+        if let Some(fees) = client_ledger.fees {
+            self.fees.put(fees);
+        }
+    }
+}
+
+/// Balances and fee totals of a single client, as moved between ledgers.
+#[derive(Debug)]
+pub struct ClientLedger {
+    available: Option<Decimal>,
+    held: Option<Decimal>,
+    fees: Option<FeeReportLine>,
+}
+
+/// Balance of a single ledger account, reported in either debit or credit column.
+#[derive(Serialize, Debug, Eq, PartialEq)]
+pub struct TrialBalanceLine {
+    #[serde(serialize_with = "serialize_display")]
+    pub account: LedgerAccount,
+    #[serde(serialize_with = "serialize_decimal_4_places")]
+    pub debit: Decimal,
+    #[serde(serialize_with = "serialize_decimal_4_places")]
+    pub credit: Decimal,
+}
+
+/// TrialBalance lists balances of all ledger accounts, books balance when total debits equal total credits.
+#[derive(Debug, Eq, PartialEq)]
+pub struct TrialBalance {
+    pub lines: Vec<TrialBalanceLine>,
+    pub total_debit: Decimal,
+    pub total_credit: Decimal,
+}
+
+impl TrialBalance {
+    /// Builds the report from (possibly repeated) account balances, eg. merged from multiple ledgers.
+    pub fn from_balances(balances: impl IntoIterator<Item = (LedgerAccount, Decimal)>) -> Self {
+        let mut merged = BTreeMap::<LedgerAccount, Decimal>::new();
+        for (account, balance) in balances {
+            *merged.entry(account).or_default() += balance;
+        }
+        let lines = merged
+            .into_iter()
+            .map(|(account, balance)| TrialBalanceLine {
+                account,
+                debit: if balance.is_sign_negative() {
+                    -balance
+                } else {
+                    Decimal::ZERO
+                },
+                credit: if balance.is_sign_negative() {
+                    Decimal::ZERO
+                } else {
+                    balance
+                },
+            })
+            .collect::<Vec<_>>();
+        let total_debit = lines.iter().map(|l| l.debit).sum();
+        let total_credit = lines.iter().map(|l| l.credit).sum();
+        TrialBalance {
+            lines,
+            total_debit,
+            total_credit,
+        }
+    }
+
+    pub fn is_balanced(&self) -> bool {
+        self.total_debit == self.total_credit
+    }
+}
+
+pub(crate) fn serialize_display<T, S>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: fmt::Display,
+    S: Serializer,
+{
+    serializer.collect_str(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::to_csv_string;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_post_unbalanced() {
+        let mut ledger = Ledger::default();
+        let res = ledger.post(JournalEntry {
+            kind: JournalEntryKind::Deposit,
+            client_id: 1,
+            txn_id: 101,
+            postings: vec![
+                Posting {
+                    account: LedgerAccount::Settlement,
+                    amount: dec!(-10),
+                },
+                Posting {
+                    account: LedgerAccount::CustomerAvailable(1),
+                    amount: dec!(9.99),
+                },
+            ],
+        });
+        assert!(res.unwrap_err().to_string().contains("unbalanced"));
+        assert!(ledger.trial_balance().lines.is_empty());
+    }
+
+    #[test]
+    fn test_trial_balance() {
+        let mut ledger = Ledger::default();
+        ledger.transfer(
+            JournalEntryKind::Deposit,
+            1,
+            101,
+            LedgerAccount::Settlement,
+            LedgerAccount::CustomerAvailable(1),
+            dec!(100),
+        );
+        ledger.transfer(
+            JournalEntryKind::Dispute,
+            1,
+            101,
+            LedgerAccount::CustomerAvailable(1),
+            LedgerAccount::CustomerHeld(1),
+            dec!(100),
+        );
+        ledger.transfer(
+            JournalEntryKind::Chargeback,
+            1,
+            101,
+            LedgerAccount::CustomerHeld(1),
+            LedgerAccount::ChargebackLoss,
+            dec!(100),
+        );
+
+        let trial_balance = ledger.trial_balance();
+        assert!(trial_balance.is_balanced());
+        assert_eq!(dec!(100), trial_balance.total_debit);
+        assert_eq!(
+            "account,debit,credit
+client:1:available,0,0
+client:1:held,0,0
+settlement,100,0
+chargeback_loss,0,100",
+            to_csv_string(&trial_balance.lines).unwrap()
+        );
+    }
+}
@@ -1,6 +1,23 @@
 pub mod account;
+pub mod audit;
+pub mod concurrent;
+pub mod config;
 pub mod decimal;
+pub mod disputes;
+pub mod error;
+pub mod fee;
+pub mod generator;
+pub mod ingest;
+pub mod ledger;
+pub mod limits;
+pub mod observer;
+pub mod operations;
 pub mod payment_engine;
+pub mod pipeline;
+pub mod retention;
+pub mod rules;
+pub mod sharded;
 pub mod txn;
+pub mod txn_table;
 pub mod types;
 pub mod util;
@@ -0,0 +1,310 @@
+use crate::{
+    account::serialize_decimal_4_places,
+    config::SECONDS_PER_DAY,
+    error::{EngineError, Operation},
+    types::{ClientId, Timestamp},
+};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, collections::VecDeque, fmt};
+
+/// Name of the tier applying to clients without an assigned tier.
+pub const DEFAULT_TIER: &str = "default";
+
+/// Limits of a single client tier, unlimited if `None`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TierLimits {
+    /// Maximum amount of a single deposit
+    pub max_deposit: Option<Decimal>,
+    /// Maximum amount of a single withdrawal
+    pub max_withdrawal: Option<Decimal>,
+    /// Maximum total withdrawn per (UTC) day
+    pub max_daily_withdrawal: Option<Decimal>,
+    /// Maximum number of withdrawals within the sliding `window_hours`
+    pub max_withdrawals_per_window: Option<u32>,
+    /// Sliding window for `max_withdrawals_per_window`
+    pub window_hours: u32,
+}
+
+impl Default for TierLimits {
+    fn default() -> Self {
+        TierLimits {
+            max_deposit: None,
+            max_withdrawal: None,
+            max_daily_withdrawal: None,
+            max_withdrawals_per_window: None,
+            window_hours: 24,
+        }
+    }
+}
+
+/// Limits per client tier, no limits apply by default.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    /// Limits per tier name, the `default` tier applies to clients without an assigned tier
+    pub tiers: BTreeMap<String, TierLimits>,
+    /// Tier name per client
+    pub clients: BTreeMap<ClientId, String>,
+}
+
+impl LimitsConfig {
+    /// Tier name and limits applying to the client, if any.
+    pub fn tier(&self, client_id: ClientId) -> Option<(&str, &TierLimits)> {
+        let name = self
+            .clients
+            .get(&client_id)
+            .map(String::as_str)
+            .unwrap_or(DEFAULT_TIER);
+        self.tiers
+            .get_key_value(name)
+            .map(|(name, limits)| (name.as_str(), limits))
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if let Some((client_id, tier)) = self
+            .clients
+            .iter()
+            .find(|(_, tier)| !self.tiers.contains_key(*tier))
+        {
+            anyhow::bail!("Unknown tier {tier} of client {client_id}")
+        }
+        Ok(())
+    }
+}
+
+/// Limits that can be exceeded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitKind {
+    Deposit,
+    Withdrawal,
+    DailyWithdrawal,
+    WithdrawalCount,
+}
+
+impl fmt::Display for LimitKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            LimitKind::Deposit => "deposit",
+            LimitKind::Withdrawal => "withdrawal",
+            LimitKind::DailyWithdrawal => "daily withdrawal",
+            LimitKind::WithdrawalCount => "withdrawal count",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Usage of limits by a single client.
+/// Note: without event timestamps, all withdrawals fall within the same day and window.
+#[derive(Debug, Default)]
+pub struct LimitUsage {
+    /// Day, since the unix epoch, of `daily_withdrawn`
+    day: Timestamp,
+    daily_withdrawn: Decimal,
+    /// Times of withdrawals within the window
+    recent_withdrawals: VecDeque<Timestamp>,
+    /// Number of operations rejected due to limits
+    rejections: u32,
+}
+
+impl LimitUsage {
+    pub fn check_deposit(
+        &mut self,
+        limits: &TierLimits,
+        amount: Decimal,
+    ) -> Result<(), EngineError> {
+        let res = check(
+            Operation::Deposit,
+            LimitKind::Deposit,
+            limits.max_deposit,
+            amount,
+        );
+        self.count_rejection(res)
+    }
+
+    /// Checks the withdrawal, or outgoing transfer, against all limits, rolling usage forward to `now`.
+    pub fn check_withdrawal(
+        &mut self,
+        operation: Operation,
+        limits: &TierLimits,
+        amount: Decimal,
+        now: Option<Timestamp>,
+    ) -> Result<(), EngineError> {
+        let now = now.unwrap_or_default();
+        let day = now / SECONDS_PER_DAY;
+        if day != self.day {
+            self.day = day;
+            self.daily_withdrawn = Decimal::ZERO;
+        }
+        let window = Timestamp::from(limits.window_hours) * 60 * 60;
+        while self
+            .recent_withdrawals
+            .front()
+            .is_some_and(|&at| at.saturating_add(window) <= now)
+        {
+            self.recent_withdrawals.pop_front();
+        }
+
+        let res = check(
+            operation,
+            LimitKind::Withdrawal,
+            limits.max_withdrawal,
+            amount,
+        )
+        .and_then(|_| {
+            check(
+                operation,
+                LimitKind::DailyWithdrawal,
+                limits.max_daily_withdrawal,
+                self.daily_withdrawn + amount,
+            )
+        })
+        .and_then(|_| {
+            check(
+                operation,
+                LimitKind::WithdrawalCount,
+                limits.max_withdrawals_per_window.map(Decimal::from),
+                Decimal::from(self.recent_withdrawals.len() + 1),
+            )
+        });
+        self.count_rejection(res)
+    }
+
+    /// Records a successful withdrawal, following `check_withdrawal()`.
+    pub fn record_withdrawal(&mut self, amount: Decimal, now: Option<Timestamp>) {
+        self.daily_withdrawn += amount;
+        self.recent_withdrawals.push_back(now.unwrap_or_default());
+    }
+
+    fn count_rejection(&mut self, res: Result<(), EngineError>) -> Result<(), EngineError> {
+        if res.is_err() {
+            self.rejections += 1;
+        }
+        res
+    }
+}
+
+fn check(
+    operation: Operation,
+    kind: LimitKind,
+    limit: Option<Decimal>,
+    value: Decimal,
+) -> Result<(), EngineError> {
+    match limit {
+        Some(limit) if value > limit => Err(EngineError::LimitExceeded {
+            operation,
+            kind,
+            limit,
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Limit usage of a single client.
+#[derive(Serialize, Debug, Eq, PartialEq)]
+pub struct LimitReportLine {
+    #[serde(rename = "client")]
+    pub client_id: ClientId,
+    pub tier: String,
+    #[serde(serialize_with = "serialize_decimal_4_places")]
+    pub daily_withdrawn: Decimal,
+    pub window_withdrawals: usize,
+    pub rejections: u32,
+}
+
+impl LimitReportLine {
+    pub fn new(client_id: ClientId, tier: &str, usage: &LimitUsage) -> Self {
+        LimitReportLine {
+            client_id,
+            tier: tier.to_owned(),
+            daily_withdrawn: usage.daily_withdrawn,
+            window_withdrawals: usage.recent_withdrawals.len(),
+            rejections: usage.rejections,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_tier() {
+        let config: LimitsConfig = serde_json::from_str(
+            r#"{
+                "tiers": {"default": {"max_withdrawal": 100}, "vip": {}},
+                "clients": {"2": "vip"}
+            }"#,
+        )
+        .unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(
+            Some(dec!(100)),
+            config
+                .tier(1)
+                .map(|(_, limits)| limits.max_withdrawal)
+                .unwrap()
+        );
+        assert_eq!(Some("vip"), config.tier(2).map(|(name, _)| name));
+        assert!(LimitsConfig::default().tier(1).is_none());
+
+        let config: LimitsConfig = serde_json::from_str(r#"{"clients": {"2": "vip"}}"#).unwrap();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_withdrawal_limits() {
+        let limits = TierLimits {
+            max_withdrawal: Some(dec!(50)),
+            max_daily_withdrawal: Some(dec!(80)),
+            max_withdrawals_per_window: Some(2),
+            window_hours: 1,
+            ..Default::default()
+        };
+        let mut usage = LimitUsage::default();
+        let mut withdraw = |amount, now| {
+            usage
+                .check_withdrawal(Operation::Withdraw, &limits, amount, Some(now))
+                .inspect(|_| usage.record_withdrawal(amount, Some(now)))
+        };
+
+        assert_eq!(
+            Err(EngineError::LimitExceeded {
+                operation: Operation::Withdraw,
+                kind: LimitKind::Withdrawal,
+                limit: dec!(50)
+            }),
+            withdraw(dec!(51), 0)
+        );
+        assert!(withdraw(dec!(50), 0).is_ok());
+        assert!(withdraw(dec!(10), 60).is_ok());
+        // 3rd withdrawal within the hour
+        assert_eq!(
+            Err(EngineError::LimitExceeded {
+                operation: Operation::Withdraw,
+                kind: LimitKind::WithdrawalCount,
+                limit: dec!(2)
+            }),
+            withdraw(dec!(10), 120)
+        );
+        // 90 within the same day
+        assert_eq!(
+            Err(EngineError::LimitExceeded {
+                operation: Operation::Withdraw,
+                kind: LimitKind::DailyWithdrawal,
+                limit: dec!(80)
+            }),
+            withdraw(dec!(30), 3600)
+        );
+        assert!(withdraw(dec!(20), 3600).is_ok());
+        // next day
+        assert!(withdraw(dec!(50), SECONDS_PER_DAY).is_ok());
+
+        let line = LimitReportLine::new(1, DEFAULT_TIER, &usage);
+        assert_eq!(dec!(50), line.daily_withdrawn);
+        assert_eq!(1, line.window_withdrawals);
+        assert_eq!(3, line.rejections);
+    }
+}
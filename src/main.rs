@@ -1,11 +1,96 @@
+use clap::{Parser, Subcommand, ValueEnum};
 use payments_engine::{
+    config::EngineConfig,
+    disputes::open_disputes_report,
+    ingest::ingest,
     payment_engine::{InMemoryPaymentEngine, PaymentEngine},
+    pipeline::{read_csv_parallel, PipelineOptions},
+    rules::RuleChain,
+    sharded::ShardedPaymentEngine,
     util::{read_csv_file, to_csv_string},
 };
-use std::fs::File;
-use tracing::warn;
+use std::{fs::File, num::NonZeroUsize, path::PathBuf};
+use tracing::{debug, info, warn};
 use tracing_subscriber::EnvFilter;
 
+/// Processes transactions from the input csv, writing account snapshots, or the requested report, to stdout.
+#[derive(Parser, Debug)]
+struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Input transactions csv
+    input: PathBuf,
+
+    /// Engine config file, TOML or JSON (by `.json` extension)
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// Print the ledger trial balance to stderr
+    #[arg(long)]
+    trial_balance: bool,
+
+    /// Audit accounts against their recorded transactions, failing on drift
+    #[arg(long)]
+    verify: bool,
+
+    /// Print fees charged per client to stderr
+    #[arg(long)]
+    fee_report: bool,
+
+    /// Print limit usage and rejections per client to stderr
+    #[arg(long)]
+    limit_report: bool,
+
+    /// Print retained and evicted transactions, and approximate memory usage, per client to stderr
+    #[arg(long)]
+    memory_report: bool,
+
+    /// Print events flagged by rules, for review, to stderr
+    #[arg(long)]
+    flag_report: bool,
+
+    /// Print the batch summary, as JSON, to stderr
+    #[arg(long)]
+    summary: bool,
+
+    /// Write the batch summary, as JSON, to the file
+    #[arg(long)]
+    summary_file: Option<PathBuf>,
+
+    /// Process events in parallel, partitioning accounts by client across the number of shards.
+    /// Transfers across shards pause processing, moving the parties onto the same shard
+    #[arg(long, value_parser = clap::value_parser!(u16).range(1..))]
+    shards: Option<u16>,
+
+    /// Parse the input on the number of threads, feeding events into the engine in original order
+    #[arg(long)]
+    parse_threads: Option<NonZeroUsize>,
+
+    /// Records per chunk handed to a parser thread
+    #[arg(long, default_value = "1024", requires = "parse_threads")]
+    chunk_size: NonZeroUsize,
+
+    /// Parsed chunks buffered ahead of the engine, stalling the reader when full
+    #[arg(long, default_value = "64", requires = "parse_threads")]
+    parse_buffer: NonZeroUsize,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// List transactions currently under dispute, instead of account snapshots
+    OpenDisputes {
+        #[arg(long, value_enum, default_value_t = Format::Csv)]
+        format: Format,
+    },
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Format {
+    Csv,
+    Json,
+}
+
 /// Main entry point, sets up logger, fetches arguments, creates `PaymentEngine`, reads in transaction events and adds them to the `PaymentEngine`.
 fn main() -> anyhow::Result<()> {
     tracing_subscriber::fmt()
@@ -13,26 +98,109 @@ fn main() -> anyhow::Result<()> {
         .with_writer(std::io::stderr)
         .init();
 
-    let args = std::env::args().collect::<Vec<String>>();
-    if args.len() != 2 {
-        anyhow::bail!("Usage: {} <input.csv>", args[0]);
-    }
-    let input_filename = &args[1];
+    let args = Args::parse();
 
+    let config = match &args.config {
+        Some(path) => EngineConfig::from_file(path)?,
+        None => EngineConfig::default(),
+    };
+    let file = File::open(&args.input)?;
+    let events: Box<dyn Iterator<Item = _>> = match args.parse_threads {
+        Some(threads) => Box::new(read_csv_parallel(
+            file,
+            PipelineOptions {
+                threads,
+                chunk_size: args.chunk_size,
+                buffer_chunks: args.parse_buffer,
+            },
+        )),
+        None => Box::new(read_csv_file(file)),
+    };
     // Pluggable PaymentEngine reference
-    let engine: &mut dyn PaymentEngine = &mut InMemoryPaymentEngine::default();
-    for event in read_csv_file(File::open(input_filename)?) {
-        match event {
-            Ok(event) => {
-                if let Err(err) = engine.add_event(event) {
-                    warn!(?err, "Error processing event") // Note: skipping errors
-                }
-            }
-            Err(err) => warn!(?err, "Error reading event"), // Note: skipping errors
+    let (engine, summary): (Box<dyn PaymentEngine>, _) = match args.shards {
+        Some(shards) => {
+            let rules_config = config.rules.clone();
+            let mut engine = ShardedPaymentEngine::new(shards.into(), config);
+            let summary =
+                engine.ingest_parallel(events, || RuleChain::from_config(&rules_config))?;
+            (Box::new(engine), summary)
+        }
+        None => {
+            let mut rules = RuleChain::from_config(&config.rules);
+            let mut engine = InMemoryPaymentEngine::new(config);
+            let summary = ingest(events, &mut rules, &mut engine)?;
+            (Box::new(engine), summary)
         }
+    };
+    let engine = engine.as_ref();
+    debug!(
+        events = summary.events(),
+        rejected = summary.rejected,
+        events_per_sec = summary.events_per_sec,
+        "Batch processed"
+    );
+    if args.summary {
+        eprintln!("{}", serde_json::to_string_pretty(&summary)?);
+    }
+    if let Some(path) = &args.summary_file {
+        serde_json::to_writer_pretty(File::create(path)?, &summary)?;
     }
 
     let snapshots = engine.snapshots()?;
-    println!("{}", to_csv_string(&snapshots)?);
+    match args.command {
+        None => println!("{}", to_csv_string(&snapshots)?),
+        Some(Command::OpenDisputes { format }) => {
+            let disputes = open_disputes_report(engine)?;
+            match format {
+                Format::Csv => println!("{}", to_csv_string(&disputes)?),
+                Format::Json => println!("{}", serde_json::to_string_pretty(&disputes)?),
+            }
+        }
+    }
+
+    let trial_balance = engine.trial_balance()?;
+    if args.trial_balance {
+        eprintln!("{}", to_csv_string(&trial_balance.lines)?);
+    }
+    if !trial_balance.is_balanced() {
+        anyhow::bail!(
+            "Books do not balance, debit: {}, credit: {}",
+            trial_balance.total_debit,
+            trial_balance.total_credit
+        );
+    }
+    debug!(total = %trial_balance.total_debit, "Books balance");
+
+    if args.fee_report {
+        eprintln!("{}", to_csv_string(&engine.fee_report()?)?);
+    }
+    if args.limit_report {
+        eprintln!("{}", to_csv_string(&engine.limit_report()?)?);
+    }
+
+    if args.flag_report {
+        if summary.flagged > summary.flags.len() as u64 {
+            warn!(
+                flagged = summary.flagged,
+                listed = summary.flags.len(),
+                "Flag report truncated, see the summary for counts per rule"
+            );
+        }
+        eprintln!("{}", to_csv_string(&summary.flags)?);
+    }
+    if args.memory_report {
+        eprintln!("{}", to_csv_string(&engine.memory_report()?)?);
+    }
+
+    if args.verify {
+        let drifts = engine.audit()?;
+        for drift in &drifts {
+            warn!(?drift, "Account drift");
+        }
+        if !drifts.is_empty() {
+            anyhow::bail!("Audit found {} account drift(s)", drifts.len());
+        }
+        info!(accounts = snapshots.len(), "Audit passed");
+    }
     Ok(())
 }
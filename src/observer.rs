@@ -0,0 +1,35 @@
+use crate::{
+    account::AccountSnapshot,
+    types::{ClientId, TxnId},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotificationKind {
+    Deposited,
+    Withdrawn,
+    /// Incoming and outgoing transfers are notified to both sides
+    Transferred,
+    DisputeOpened,
+    DisputeResolved,
+    ChargedBack,
+    Refunded,
+    Reversed,
+    AccountLocked,
+}
+
+/// State change of a single account, caused by the transaction.
+/// Accounts created by the transaction start off from zero balances.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notification {
+    pub kind: NotificationKind,
+    pub client_id: ClientId,
+    pub txn_id: TxnId,
+    pub before: AccountSnapshot,
+    pub after: AccountSnapshot,
+}
+
+/// Receives notifications synchronously, as soon as the engine state changes.
+/// Note: implementations should be quick to return, offloading any heavy lifting, eg. onto a channel.
+pub trait EngineObserver: Send + Sync {
+    fn notify(&self, notification: &Notification);
+}
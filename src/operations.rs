@@ -0,0 +1,222 @@
+use crate::{
+    account::Account,
+    config::EngineConfig,
+    error::{EngineError, Operation},
+    ledger::{JournalEntry, JournalEntryKind, LedgerAccount, Posting},
+    txn::{Txn, TxnAction, TxnType},
+    types::{ClientId, Timestamp, TxnId},
+};
+use rust_decimal::Decimal;
+use std::collections::BTreeMap;
+
+/// Credits the account with the deposit, allowed even if locked.
+/// Note: operations are shared by engines, which look up and lock accounts, apply engine wide policies
+/// (limits, retention, notifications), and post the returned journal entries to their ledger
+pub fn deposit(
+    (client_id, acc): (ClientId, &mut Account),
+    txn_id: TxnId,
+    amount: Decimal,
+    now: Option<Timestamp>,
+) -> Result<JournalEntry, EngineError> {
+    acc.insert_txn(txn_id, Txn::new(TxnType::Deposit, amount, now))?;
+    acc.available += amount;
+    Ok(JournalEntry::transfer(
+        JournalEntryKind::Deposit,
+        client_id,
+        txn_id,
+        LedgerAccount::Settlement,
+        LedgerAccount::CustomerAvailable(client_id),
+        amount,
+    ))
+}
+
+/// Debits the account with the withdrawal and its fee, if available funds cover both.
+/// Note: locked accounts are rejected by the engine, ahead of its limits
+pub fn withdraw(
+    (client_id, acc): (ClientId, &mut Account),
+    txn_id: TxnId,
+    amount: Decimal,
+    config: &EngineConfig,
+    now: Option<Timestamp>,
+) -> Result<Vec<JournalEntry>, EngineError> {
+    let fee = config.fees.withdrawal_fee(amount);
+    if acc.available < amount + fee {
+        return Err(EngineError::InsufficientFunds(Operation::Withdraw));
+    }
+    let mut txn = Txn::new(TxnType::Withdrawal, amount, now);
+    txn.fee = fee;
+    acc.insert_txn(txn_id, txn)?;
+    acc.available -= amount + fee;
+    let mut entries = vec![JournalEntry::transfer(
+        JournalEntryKind::Withdrawal,
+        client_id,
+        txn_id,
+        LedgerAccount::CustomerAvailable(client_id),
+        LedgerAccount::Settlement,
+        amount,
+    )];
+    if !fee.is_zero() {
+        entries.push(JournalEntry::transfer(
+            JournalEntryKind::WithdrawalFee,
+            client_id,
+            txn_id,
+            LedgerAccount::CustomerAvailable(client_id),
+            LedgerAccount::House,
+            fee,
+        ));
+    }
+    Ok(entries)
+}
+
+/// Checks the transfer can be applied, the destination account being `None` if yet to be opened.
+/// Disallowed for locked source account, whilst locked destination accounts accept incoming funds, same as deposits.
+pub fn check_transfer(
+    acc: &Account,
+    dest_acc: Option<&Account>,
+    txn_id: TxnId,
+    amount: Decimal,
+) -> Result<(), EngineError> {
+    if acc.locked {
+        return Err(EngineError::AccountLocked(Operation::Transfer));
+    }
+    if acc.available < amount {
+        return Err(EngineError::InsufficientFunds(Operation::Transfer));
+    }
+    acc.check_insert_txn(txn_id)?;
+    if let Some(dest_acc) = dest_acc {
+        dest_acc.check_insert_txn(txn_id)?;
+    }
+    Ok(())
+}
+
+/// Moves funds from the source to the destination account, recording a leg in each, once `check_transfer()` passed.
+pub fn transfer(
+    (client_id, acc): (ClientId, &mut Account),
+    (destination_client_id, dest_acc): (ClientId, &mut Account),
+    txn_id: TxnId,
+    amount: Decimal,
+    now: Option<Timestamp>,
+) -> Result<JournalEntry, EngineError> {
+    let counterparty = destination_client_id;
+    acc.insert_txn(
+        txn_id,
+        Txn::new(TxnType::TransferOut { counterparty }, amount, now),
+    )?;
+    acc.available -= amount;
+    let counterparty = client_id;
+    dest_acc.insert_txn(
+        txn_id,
+        Txn::new(TxnType::TransferIn { counterparty }, amount, now),
+    )?;
+    dest_acc.available += amount;
+    Ok(JournalEntry::transfer(
+        JournalEntryKind::Transfer,
+        client_id,
+        txn_id,
+        LedgerAccount::CustomerAvailable(client_id),
+        LedgerAccount::CustomerAvailable(destination_client_id),
+        amount,
+    ))
+}
+
+/// Applies the action on all legs of the transaction, moving funds between available, held, charged back and settlement.
+/// The 1st account is the issuing client's, followed by the counterparty's for transfers, acted upon as a single unit.
+/// All legs are validated prior to any mutation. Chargebacks lock the issuing account, charging the chargeback fee.
+/// Locked accounts only accept refunds, same as deposits, unless `allow_locked`, eg. for time driven resolves.
+pub fn transition(
+    accs: &mut [(ClientId, &mut Account)],
+    txn_id: TxnId,
+    action: TxnAction,
+    amount: Option<Decimal>,
+    config: &EngineConfig,
+    now: Option<Timestamp>,
+    allow_locked: bool,
+) -> Result<Vec<JournalEntry>, EngineError> {
+    let operation = action.operation();
+    let kind = match action {
+        TxnAction::Dispute => JournalEntryKind::Dispute,
+        TxnAction::Resolve => JournalEntryKind::Resolve,
+        TxnAction::Chargeback => JournalEntryKind::Chargeback,
+        TxnAction::Refund => JournalEntryKind::Refund,
+        TxnAction::Reverse => JournalEntryKind::Reversal,
+    };
+    let client_id = accs[0].0;
+    if accs[0].1.locked && action != TxnAction::Refund && !allow_locked {
+        return Err(EngineError::AccountLocked(operation));
+    }
+    let Some(txn) = accs[0].1.txns.get(&txn_id) else {
+        return Err(EngineError::TxnNotFound(operation));
+    };
+    match txn.txn_type {
+        TxnType::TransferIn { .. } => return Err(EngineError::IncomingTransfer(operation)),
+        TxnType::TransferOut { counterparty }
+            if accs.get(1).map(|(id, _)| *id) != Some(counterparty) =>
+        {
+            return Err(EngineError::TxnNotFound(operation));
+        }
+        _ => (),
+    }
+
+    // Validate all legs prior to any mutation
+    let mut legs = Vec::with_capacity(accs.len());
+    for (_, acc) in accs.iter() {
+        let Some(mut leg) = acc.txns.get(&txn_id) else {
+            return Err(EngineError::TxnNotFound(operation));
+        };
+        let movement = leg.transition(action, amount, config, now, acc.events)?;
+        if action == TxnAction::Reverse && acc.available + movement.available() < Decimal::ZERO {
+            return Err(EngineError::InsufficientFunds(operation));
+        }
+        legs.push((leg, movement));
+    }
+
+    let mut postings = BTreeMap::<LedgerAccount, Decimal>::new();
+    for ((leg_client_id, acc), (leg, movement)) in accs.iter_mut().zip(legs) {
+        acc.held += movement.held;
+        acc.available += movement.available();
+        acc.txns.insert(txn_id, leg);
+        for (account, amount) in [
+            (
+                LedgerAccount::CustomerAvailable(*leg_client_id),
+                movement.available(),
+            ),
+            (LedgerAccount::CustomerHeld(*leg_client_id), movement.held),
+            (LedgerAccount::ChargebackLoss, movement.charged_back),
+            (LedgerAccount::Settlement, movement.settled),
+        ] {
+            *postings.entry(account).or_default() += amount;
+        }
+    }
+    // Note: transfer legs' chargebacks cancel out, as funds stay within the engine
+    let postings = postings
+        .into_iter()
+        .filter(|(_, amount)| !amount.is_zero())
+        .map(|(account, amount)| Posting { account, amount })
+        .collect();
+    let mut entries = vec![JournalEntry {
+        kind,
+        client_id,
+        txn_id,
+        postings,
+    }];
+
+    if action == TxnAction::Chargeback {
+        let acc = &mut accs[0].1;
+        acc.locked = true;
+        let fee = config.fees.chargeback_fee;
+        if !fee.is_zero() {
+            // Note: chargeback fees can take the available balance negative
+            acc.available -= fee;
+            acc.txns.update(&txn_id, |txn| txn.fee += fee);
+            entries.push(JournalEntry::transfer(
+                JournalEntryKind::ChargebackFee,
+                client_id,
+                txn_id,
+                LedgerAccount::CustomerAvailable(client_id),
+                LedgerAccount::House,
+                fee,
+            ));
+        }
+    }
+    Ok(entries)
+}
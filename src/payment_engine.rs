@@ -1,10 +1,23 @@
 use crate::{
     account::{Account, AccountSnapshot},
+    audit::AccountDrift,
+    config::{EngineConfig, SECONDS_PER_DAY},
     decimal::PositiveDecimal,
-    txn::{Txn, TxnType},
-    types::{ClientId, TxnEvent, TxnEventDetail, TxnId},
+    error::{EngineError, Operation},
+    fee::FeeReportLine,
+    ledger::{ClientLedger, Ledger, TrialBalance},
+    limits::{LimitReportLine, LimitUsage},
+    observer::{EngineObserver, Notification, NotificationKind},
+    operations,
+    retention::{Archived, ArchivedClient, MemoryReportLine, TxnArchive},
+    txn::{TxnAction, TxnState, TxnType, TxnView},
+    types::{ClientId, Timestamp, TxnEvent, TxnEventDetail, TxnId},
 };
-use std::collections::BTreeMap;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+};
+use tracing::{info, warn};
 
 pub trait PaymentEngine {
     fn deposit(
@@ -21,15 +34,81 @@ pub trait PaymentEngine {
         amount: PositiveDecimal,
     ) -> anyhow::Result<()>;
 
-    fn dispute(&mut self, client_id: ClientId, txn_id: TxnId) -> anyhow::Result<()>;
+    fn dispute(
+        &mut self,
+        client_id: ClientId,
+        txn_id: TxnId,
+        amount: Option<PositiveDecimal>,
+    ) -> anyhow::Result<()>;
 
     fn resolve(&mut self, client_id: ClientId, txn_id: TxnId) -> anyhow::Result<()>;
 
-    fn chargeback(&mut self, client_id: ClientId, txn_id: TxnId) -> anyhow::Result<()>;
+    fn chargeback(
+        &mut self,
+        client_id: ClientId,
+        txn_id: TxnId,
+        amount: Option<PositiveDecimal>,
+    ) -> anyhow::Result<()>;
+
+    /// Moves funds between accounts atomically.
+    fn transfer(
+        &mut self,
+        client_id: ClientId,
+        txn_id: TxnId,
+        destination_client_id: ClientId,
+        amount: PositiveDecimal,
+    ) -> anyhow::Result<()>;
+
+    /// Credits back (a portion of) an earlier withdrawal.
+    fn refund(
+        &mut self,
+        client_id: ClientId,
+        txn_id: TxnId,
+        amount: Option<PositiveDecimal>,
+    ) -> anyhow::Result<()>;
+
+    /// Voids an earlier deposit or withdrawal.
+    fn reverse(&mut self, client_id: ClientId, txn_id: TxnId) -> anyhow::Result<()>;
+
+    /// Moves the engine clock to the event time, applying time based policies.
+    fn advance_clock(&mut self, timestamp: Timestamp) -> anyhow::Result<()>;
+
+    /// Latest event time seen, if any.
+    fn now(&self) -> Option<Timestamp>;
 
     fn snapshots(&self) -> anyhow::Result<Vec<AccountSnapshot>>;
 
+    /// Snapshot of a single account, if it exists.
+    fn account(&self, client_id: ClientId) -> anyhow::Result<Option<AccountSnapshot>>;
+
+    /// View of a single transaction of the client, if it exists.
+    fn transaction(&self, client_id: ClientId, txn_id: TxnId) -> anyhow::Result<Option<TxnView>>;
+
+    /// Transactions of the client currently under dispute, ordered by transaction id.
+    fn disputed_transactions(&self, client_id: ClientId) -> anyhow::Result<Vec<TxnView>>;
+
+    /// Balances of all ledger accounts, proving the books balance.
+    fn trial_balance(&self) -> anyhow::Result<TrialBalance>;
+
+    /// Recomputes every account from its recorded transactions, reporting drift per client.
+    fn audit(&self) -> anyhow::Result<Vec<AccountDrift>>;
+
+    /// Fees charged per client.
+    fn fee_report(&self) -> anyhow::Result<Vec<FeeReportLine>>;
+
+    /// Limit usage and rejections per client subject to limits.
+    fn limit_report(&self) -> anyhow::Result<Vec<LimitReportLine>>;
+
+    /// Retained and evicted transactions, and approximate memory usage, per client.
+    fn memory_report(&self) -> anyhow::Result<Vec<MemoryReportLine>>;
+
+    /// Registers the observer, notified of every subsequent account state change.
+    fn register_observer(&mut self, observer: Arc<dyn EngineObserver>);
+
     fn add_event(&mut self, event: TxnEvent) -> anyhow::Result<()> {
+        if let Some(timestamp) = event.timestamp {
+            self.advance_clock(timestamp)?;
+        }
         match event.detail {
             TxnEventDetail::Deposit { amount } => {
                 self.deposit(event.client_id, event.txn_id, amount)
@@ -39,18 +118,288 @@ pub trait PaymentEngine {
                 self.withdraw(event.client_id, event.txn_id, amount)
             }
 
-            TxnEventDetail::Dispute => self.dispute(event.client_id, event.txn_id),
+            TxnEventDetail::Dispute { amount } => {
+                self.dispute(event.client_id, event.txn_id, amount)
+            }
 
             TxnEventDetail::Resolve => self.resolve(event.client_id, event.txn_id),
 
-            TxnEventDetail::Chargeback => self.chargeback(event.client_id, event.txn_id),
+            TxnEventDetail::Chargeback { amount } => {
+                self.chargeback(event.client_id, event.txn_id, amount)
+            }
+
+            TxnEventDetail::Transfer {
+                destination_client_id,
+                amount,
+            } => self.transfer(event.client_id, event.txn_id, destination_client_id, amount),
+
+            TxnEventDetail::Refund { amount } => self.refund(event.client_id, event.txn_id, amount),
+
+            TxnEventDetail::Reversal => self.reverse(event.client_id, event.txn_id),
         }
     }
 }
 
+/// State of a single client within an `InMemoryPaymentEngine`, as moved between engines.
+/// Note: clients party to the same transfer are moved together, as its legs are acted upon as a single unit
+pub struct ClientState {
+    acc: Option<Account>,
+    ledger: ClientLedger,
+    limit_usage: Option<LimitUsage>,
+    deadlines: Vec<(Timestamp, ClientId, TxnId)>,
+    archived: ArchivedClient,
+}
+
 #[derive(Default)]
 pub struct InMemoryPaymentEngine {
+    config: EngineConfig,
     accs: BTreeMap<ClientId, Account>,
+    ledger: Ledger,
+    /// Latest event time seen, driving time based policies
+    now: Option<Timestamp>,
+    /// Auto resolve deadlines of open disputes
+    dispute_deadlines: BTreeSet<(Timestamp, ClientId, TxnId)>,
+    /// Usage of limits, for clients subject to limits
+    limit_usage: BTreeMap<ClientId, LimitUsage>,
+    observers: Vec<Arc<dyn EngineObserver>>,
+    /// Transactions evicted as per `EngineConfig::retention`
+    archive: TxnArchive,
+    /// Day of the latest sweep of all accounts for expired transactions
+    swept_day: Option<Timestamp>,
+}
+
+impl InMemoryPaymentEngine {
+    pub fn new(config: EngineConfig) -> Self {
+        InMemoryPaymentEngine {
+            config,
+            ..Default::default()
+        }
+    }
+
+    pub fn ledger(&self) -> &Ledger {
+        &self.ledger
+    }
+
+    /// Takes all state of the client out of the engine, eg. to move it to another shard.
+    pub fn take_client(&mut self, client_id: ClientId) -> anyhow::Result<ClientState> {
+        let deadlines = self
+            .dispute_deadlines
+            .iter()
+            .filter(|(_, id, _)| *id == client_id)
+            .copied()
+            .collect::<Vec<_>>();
+        for deadline in &deadlines {
+            self.dispute_deadlines.remove(deadline);
+        }
+        Ok(ClientState {
+            acc: self.accs.remove(&client_id),
+            ledger: self.ledger.take_client(client_id),
+            limit_usage: self.limit_usage.remove(&client_id),
+            deadlines,
+            archived: self.archive.take_client(client_id)?,
+        })
+    }
+
+    /// Moves in all state of the client, as taken from another engine of the same config.
+    pub fn put_client(&mut self, client_id: ClientId, state: ClientState) -> anyhow::Result<()> {
+        if let Some(acc) = state.acc {
+            self.accs.insert(client_id, acc);
+        }
+        self.ledger.put_client(client_id, state.ledger);
+        if let Some(usage) = state.limit_usage {
+            self.limit_usage.insert(client_id, usage);
+        }
+        self.dispute_deadlines.extend(state.deadlines);
+        self.archive
+            .put_client(&self.config.retention, client_id, state.archived)
+    }
+
+    /// Snapshot of the account, zero balances if it does not exist yet.
+    fn account_snapshot(&self, client_id: ClientId) -> AccountSnapshot {
+        let default = Account::default();
+        let acc = self.accs.get(&client_id).unwrap_or(&default);
+        AccountSnapshot::new(client_id, acc)
+    }
+
+    /// Moves an evicted transaction back into the account, if spilled.
+    /// Note: dropped transactions are only an error for actions on recorded transactions, see `restore_recorded()`,
+    /// new ones are recorded regardless, as ids need not increase
+    fn restore(&mut self, client_id: ClientId, txn_id: TxnId) -> anyhow::Result<Archived> {
+        let Some(acc) = self.accs.get_mut(&client_id) else {
+            return Ok(Archived::NotFound);
+        };
+        if acc.evicted == 0 || acc.txns.contains_key(&txn_id) {
+            return Ok(Archived::NotFound);
+        }
+        self.archive.restore(client_id, txn_id, acc)
+    }
+
+    /// As `restore()`, failing if the transaction was dropped.
+    fn restore_recorded(
+        &mut self,
+        client_id: ClientId,
+        txn_id: TxnId,
+        operation: Operation,
+    ) -> anyhow::Result<()> {
+        match self.restore(client_id, txn_id)? {
+            Archived::Dropped => anyhow::bail!(EngineError::TxnEvicted(operation)),
+            Archived::NotFound | Archived::Spilled(_) => Ok(()),
+        }
+    }
+
+    /// Tracks a newly recorded transaction for eviction, evicting the client's oldest as needed.
+    fn retain(&mut self, client_id: ClientId, txn_id: TxnId) {
+        if !self.config.retention.is_enabled() {
+            return;
+        }
+        if let Some(acc) = self.accs.get_mut(&client_id) {
+            acc.retention_order.push_back(txn_id);
+            self.archive
+                .enforce(&self.config.retention, client_id, acc, self.now);
+        }
+    }
+
+    /// Evicts expired transactions of all accounts, once per day of event time.
+    fn sweep_expired(&mut self) {
+        let Some(day) = self
+            .now
+            .filter(|_| self.config.retention.max_age_days.is_some())
+            .map(|now| now / SECONDS_PER_DAY)
+        else {
+            return;
+        };
+        if self.swept_day == Some(day) {
+            return;
+        }
+        self.swept_day = Some(day);
+        for (&client_id, acc) in self.accs.iter_mut() {
+            self.archive
+                .enforce(&self.config.retention, client_id, acc, self.now);
+        }
+    }
+
+    fn notify(&self, kind: NotificationKind, txn_id: TxnId, before: AccountSnapshot) {
+        if self.observers.is_empty() {
+            return;
+        }
+        let notification = Notification {
+            kind,
+            client_id: before.client_id,
+            txn_id,
+            after: self.account_snapshot(before.client_id),
+            before,
+        };
+        for observer in &self.observers {
+            observer.notify(&notification);
+        }
+    }
+
+    /// Applies an action on a recorded transaction, moving funds between available, held, charged back and settlement.
+    fn transition(
+        &mut self,
+        client_id: ClientId,
+        txn_id: TxnId,
+        action: TxnAction,
+        amount: Option<PositiveDecimal>,
+    ) -> anyhow::Result<()> {
+        self.count_event(client_id);
+        self.transition_with(client_id, txn_id, action, amount, false)
+    }
+
+    /// Counts an event of the client, accepted or rejected, once its account exists, aging open disputes.
+    fn count_event(&mut self, client_id: ClientId) {
+        if let Some(acc) = self.accs.get_mut(&client_id) {
+            acc.events += 1;
+        }
+    }
+
+    /// As `transition()`, optionally allowed on locked accounts, eg. for time driven resolves.
+    fn transition_with(
+        &mut self,
+        client_id: ClientId,
+        txn_id: TxnId,
+        action: TxnAction,
+        amount: Option<PositiveDecimal>,
+        allow_locked: bool,
+    ) -> anyhow::Result<()> {
+        let operation = action.operation();
+        let notification_kind = match action {
+            TxnAction::Dispute => NotificationKind::DisputeOpened,
+            TxnAction::Resolve => NotificationKind::DisputeResolved,
+            TxnAction::Chargeback => NotificationKind::ChargedBack,
+            TxnAction::Refund => NotificationKind::Refunded,
+            TxnAction::Reverse => NotificationKind::Reversed,
+        };
+        self.restore_recorded(client_id, txn_id, operation)?;
+        let counterparty = self
+            .accs
+            .get(&client_id)
+            .and_then(|acc| acc.txns.get(&txn_id))
+            .and_then(|txn| match txn.txn_type {
+                TxnType::TransferOut { counterparty } => Some(counterparty),
+                _ => None,
+            });
+        if let Some(counterparty) = counterparty {
+            self.restore_recorded(counterparty, txn_id, operation)?;
+        }
+        let befores = [Some(client_id), counterparty]
+            .into_iter()
+            .flatten()
+            .map(|id| self.account_snapshot(id))
+            .collect::<Vec<_>>();
+        // Note: the counterparty is taken out of the map for the duration, to borrow both accounts
+        let mut counter_acc =
+            counterparty.and_then(|id| self.accs.remove(&id).map(|acc| (id, acc)));
+        let res = match self.accs.get_mut(&client_id) {
+            None => Err(EngineError::AccountNotFound(operation)),
+            Some(acc) => {
+                let mut accs = vec![(client_id, acc)];
+                if let Some((id, counter_acc)) = counter_acc.as_mut() {
+                    accs.push((*id, counter_acc));
+                }
+                operations::transition(
+                    &mut accs,
+                    txn_id,
+                    action,
+                    amount.map(|a| *a),
+                    &self.config,
+                    self.now,
+                    allow_locked,
+                )
+            }
+        };
+        if let Some((id, counter_acc)) = counter_acc {
+            self.accs.insert(id, counter_acc);
+        }
+        for entry in res? {
+            self.ledger.post(entry)?;
+        }
+
+        if action == TxnAction::Dispute {
+            let disputed_at = self.accs[&client_id]
+                .txns
+                .get(&txn_id)
+                .and_then(|txn| txn.disputed_at);
+            if let (Some(disputed_at), Some(after)) =
+                (disputed_at, self.config.auto_resolve_after())
+            {
+                self.dispute_deadlines.insert((
+                    disputed_at.saturating_add(after),
+                    client_id,
+                    txn_id,
+                ));
+            }
+        }
+        let newly_locked = action == TxnAction::Chargeback && !befores[0].locked;
+        let locked_before = befores[0].clone();
+        for before in befores {
+            self.notify(notification_kind, txn_id, before);
+        }
+        if newly_locked {
+            self.notify(NotificationKind::AccountLocked, txn_id, locked_before);
+        }
+        Ok(())
+    }
 }
 
 impl PaymentEngine for InMemoryPaymentEngine {
@@ -62,15 +411,20 @@ impl PaymentEngine for InMemoryPaymentEngine {
         txn_id: TxnId,
         amount: PositiveDecimal,
     ) -> anyhow::Result<()> {
+        self.count_event(client_id);
+        if let Some((_, limits)) = self.config.limits.tier(client_id) {
+            self.limit_usage
+                .entry(client_id)
+                .or_default()
+                .check_deposit(limits, *amount)?;
+        }
+        self.restore(client_id, txn_id)?;
+        let before = self.account_snapshot(client_id);
         let acc = self.accs.entry(client_id).or_default();
-        acc.txns.insert(
-            txn_id,
-            Txn {
-                txn_type: TxnType::Deposit,
-                amount: *amount,
-            },
-        );
-        acc.available += *amount;
+        let entry = operations::deposit((client_id, acc), txn_id, *amount, self.now)?;
+        self.ledger.post(entry)?;
+        self.retain(client_id, txn_id);
+        self.notify(NotificationKind::Deposited, txn_id, before);
         Ok(())
     }
 
@@ -82,109 +436,296 @@ impl PaymentEngine for InMemoryPaymentEngine {
         txn_id: TxnId,
         amount: PositiveDecimal,
     ) -> anyhow::Result<()> {
+        self.count_event(client_id);
+        self.restore(client_id, txn_id)?;
+        let before = self.account_snapshot(client_id);
         if let Some(acc) = self.accs.get_mut(&client_id) {
             if !acc.locked {
-                if acc.available >= *amount {
-                    acc.txns.insert(
-                        txn_id,
-                        Txn {
-                            txn_type: TxnType::Withdrawal,
-                            amount: *amount,
-                        },
-                    );
-                    acc.available -= *amount;
-                    Ok(())
-                } else {
-                    anyhow::bail!("Cannot withdraw due to insufficient funds")
+                let limits = self.config.limits.tier(client_id).map(|(_, limits)| limits);
+                if let Some(limits) = limits {
+                    self.limit_usage
+                        .entry(client_id)
+                        .or_default()
+                        .check_withdrawal(Operation::Withdraw, limits, *amount, self.now)?;
+                }
+                let entries = operations::withdraw(
+                    (client_id, acc),
+                    txn_id,
+                    *amount,
+                    &self.config,
+                    self.now,
+                )?;
+                if limits.is_some() {
+                    self.limit_usage
+                        .entry(client_id)
+                        .or_default()
+                        .record_withdrawal(*amount, self.now);
                 }
+                for entry in entries {
+                    self.ledger.post(entry)?;
+                }
+                self.retain(client_id, txn_id);
+                self.notify(NotificationKind::Withdrawn, txn_id, before);
+                Ok(())
             } else {
-                anyhow::bail!("Cannot withdraw for locked account")
+                anyhow::bail!(EngineError::AccountLocked(Operation::Withdraw))
             }
         } else {
-            anyhow::bail!("Cannot withdraw from non-existent account")
+            anyhow::bail!(EngineError::AccountNotFound(Operation::Withdraw))
         }
     }
 
-    fn dispute(&mut self, client_id: ClientId, txn_id: TxnId) -> anyhow::Result<()> {
-        if let Some(acc) = self.accs.get_mut(&client_id) {
-            if !acc.locked {
-                if let Some(txn) = acc.txns.remove(&txn_id) {
-                    let amount = txn.type_adjusted_amount();
-                    acc.held += amount;
-                    acc.available -= amount;
-                    acc.held_txns.insert(txn_id, txn);
-                    Ok(())
-                } else {
-                    anyhow::bail!("Cannot dispute non-existent transaction")
-                }
-            } else {
-                anyhow::bail!("Cannot dispute locked account")
-            }
-        } else {
-            anyhow::bail!("Cannot dispute non-existent account")
-        }
+    /// Disputes the given portion of the transaction, or all of its undisputed amount.
+    fn dispute(
+        &mut self,
+        client_id: ClientId,
+        txn_id: TxnId,
+        amount: Option<PositiveDecimal>,
+    ) -> anyhow::Result<()> {
+        self.transition(client_id, txn_id, TxnAction::Dispute, amount)
     }
 
+    /// Releases all of the disputed portion.
     fn resolve(&mut self, client_id: ClientId, txn_id: TxnId) -> anyhow::Result<()> {
-        if let Some(acc) = self.accs.get_mut(&client_id) {
-            if !acc.locked {
-                if let Some(txn) = acc.held_txns.remove(&txn_id) {
-                    let amount = txn.type_adjusted_amount();
-                    acc.held -= amount;
-                    acc.available += amount;
-                    acc.txns.insert(txn_id, txn);
-                    Ok(())
-                } else {
-                    anyhow::bail!("Cannot resolve non-disputed transaction")
-                }
-            } else {
-                anyhow::bail!("Cannot resolve locked account")
-            }
-        } else {
-            anyhow::bail!("Cannot resolve non-existent account")
+        self.transition(client_id, txn_id, TxnAction::Resolve, None)
+    }
+
+    /// Charges back the given portion of the disputed amount, or all of it, releasing the rest.
+    fn chargeback(
+        &mut self,
+        client_id: ClientId,
+        txn_id: TxnId,
+        amount: Option<PositiveDecimal>,
+    ) -> anyhow::Result<()> {
+        self.transition(client_id, txn_id, TxnAction::Chargeback, amount)
+    }
+
+    /// Transfers from the source to the destination account, recording a leg in each.
+    /// Disallowed for locked source account, whilst locked destination accounts accept incoming funds, same as deposits.
+    fn transfer(
+        &mut self,
+        client_id: ClientId,
+        txn_id: TxnId,
+        destination_client_id: ClientId,
+        amount: PositiveDecimal,
+    ) -> anyhow::Result<()> {
+        self.count_event(client_id);
+        if client_id == destination_client_id {
+            anyhow::bail!(EngineError::SelfTransfer)
+        }
+        self.restore(client_id, txn_id)?;
+        self.restore(destination_client_id, txn_id)?;
+        let befores = [
+            self.account_snapshot(client_id),
+            self.account_snapshot(destination_client_id),
+        ];
+        let Some(acc) = self.accs.get(&client_id) else {
+            anyhow::bail!(EngineError::AccountNotFound(Operation::Transfer))
+        };
+        operations::check_transfer(acc, self.accs.get(&destination_client_id), txn_id, *amount)?;
+        // Note: outgoing transfers count towards the source's withdrawal limits
+        let limits = self.config.limits.tier(client_id).map(|(_, limits)| limits);
+        if let Some(limits) = limits {
+            self.limit_usage
+                .entry(client_id)
+                .or_default()
+                .check_withdrawal(Operation::Transfer, limits, *amount, self.now)?;
+        }
+
+        // Note: the destination is taken out of the map for the duration, to borrow both accounts
+        let dest_acc = self.accs.remove(&destination_client_id);
+        let dest_existed = dest_acc.is_some();
+        let mut dest_acc = dest_acc.unwrap_or_default();
+        let res = match self.accs.get_mut(&client_id) {
+            None => Err(EngineError::AccountNotFound(Operation::Transfer)),
+            Some(acc) => operations::transfer(
+                (client_id, acc),
+                (destination_client_id, &mut dest_acc),
+                txn_id,
+                *amount,
+                self.now,
+            ),
+        };
+        if res.is_ok() || dest_existed {
+            self.accs.insert(destination_client_id, dest_acc);
         }
+        self.ledger.post(res?)?;
+        if limits.is_some() {
+            self.limit_usage
+                .entry(client_id)
+                .or_default()
+                .record_withdrawal(*amount, self.now);
+        }
+        self.retain(client_id, txn_id);
+        self.retain(destination_client_id, txn_id);
+        for before in befores {
+            self.notify(NotificationKind::Transferred, txn_id, before);
+        }
+        Ok(())
     }
 
-    fn chargeback(&mut self, client_id: ClientId, txn_id: TxnId) -> anyhow::Result<()> {
-        if let Some(acc) = self.accs.get_mut(&client_id) {
-            if !acc.locked {
-                if let Some(txn) = acc.held_txns.remove(&txn_id) {
-                    let amount = txn.type_adjusted_amount();
-                    acc.held -= amount;
-                    acc.locked = true;
-                    Ok(())
-                } else {
-                    anyhow::bail!("Cannot chargeback non-disputed transaction")
+    fn refund(
+        &mut self,
+        client_id: ClientId,
+        txn_id: TxnId,
+        amount: Option<PositiveDecimal>,
+    ) -> anyhow::Result<()> {
+        self.transition(client_id, txn_id, TxnAction::Refund, amount)
+    }
+
+    /// Reverses the deposit/withdrawal, disallowed for locked account or transactions under dispute.
+    fn reverse(&mut self, client_id: ClientId, txn_id: TxnId) -> anyhow::Result<()> {
+        self.transition(client_id, txn_id, TxnAction::Reverse, None)
+    }
+
+    /// Clock only moves forward, resolving disputes that outlived `EngineConfig::auto_resolve_days`.
+    fn advance_clock(&mut self, timestamp: Timestamp) -> anyhow::Result<()> {
+        let now = self.now.map_or(timestamp, |now| now.max(timestamp));
+        self.now = Some(now);
+        while let Some(&(deadline, client_id, txn_id)) = self.dispute_deadlines.first() {
+            if deadline >= now {
+                break;
+            }
+            self.dispute_deadlines.pop_first();
+            // Note: deadline is stale if the dispute has since been closed or re-opened
+            let still_disputed = self
+                .accs
+                .get(&client_id)
+                .and_then(|acc| acc.txns.get(&txn_id))
+                .is_some_and(|txn| {
+                    txn.state == TxnState::Disputed
+                        && txn
+                            .disputed_at
+                            .zip(self.config.auto_resolve_after())
+                            .map(|(disputed_at, after)| disputed_at.saturating_add(after))
+                            == Some(deadline)
+                });
+            if still_disputed {
+                // Note: resolves even if locked by a chargeback of another transaction, releasing the held funds
+                match self.transition_with(client_id, txn_id, TxnAction::Resolve, None, true) {
+                    Ok(()) => info!(client_id, txn_id, "Auto-resolved expired dispute"),
+                    Err(err) => warn!(?err, client_id, txn_id, "Error auto-resolving dispute"),
                 }
-            } else {
-                anyhow::bail!("Cannot chargeback locked account")
             }
-        } else {
-            anyhow::bail!("Cannot chargeback non-existent account")
         }
+        self.sweep_expired();
+        Ok(())
+    }
+
+    fn now(&self) -> Option<Timestamp> {
+        self.now
     }
 
     fn snapshots(&self) -> anyhow::Result<Vec<AccountSnapshot>> {
         let snapshots = self
             .accs
             .iter()
-            .map(|(&client_id, acc)| AccountSnapshot {
-                client_id,
-                available: acc.available,
-                held: acc.held,
-                locked: acc.locked,
-                total: acc.available + acc.held,
-            })
+            .map(|(&client_id, acc)| AccountSnapshot::new(client_id, acc))
             .collect();
         Ok(snapshots)
     }
+
+    fn account(&self, client_id: ClientId) -> anyhow::Result<Option<AccountSnapshot>> {
+        Ok(self
+            .accs
+            .get(&client_id)
+            .map(|acc| AccountSnapshot::new(client_id, acc)))
+    }
+
+    fn trial_balance(&self) -> anyhow::Result<TrialBalance> {
+        Ok(self.ledger.trial_balance())
+    }
+
+    fn fee_report(&self) -> anyhow::Result<Vec<FeeReportLine>> {
+        Ok(self.ledger.fee_report())
+    }
+
+    /// Spilled transactions are read back from disk, whilst dropped ones are no longer known.
+    fn transaction(&self, client_id: ClientId, txn_id: TxnId) -> anyhow::Result<Option<TxnView>> {
+        let Some(acc) = self.accs.get(&client_id) else {
+            return Ok(None);
+        };
+        if let Some(txn) = acc.txns.get(&txn_id) {
+            return Ok(Some(TxnView::new(client_id, txn_id, &txn, acc.events)));
+        }
+        match self.archive.get(client_id, txn_id)? {
+            Archived::Spilled(txn) => Ok(Some(TxnView::new(client_id, txn_id, &txn, acc.events))),
+            Archived::NotFound | Archived::Dropped => Ok(None),
+        }
+    }
+
+    fn disputed_transactions(&self, client_id: ClientId) -> anyhow::Result<Vec<TxnView>> {
+        let mut views = self
+            .accs
+            .get(&client_id)
+            .into_iter()
+            .flat_map(|acc| {
+                acc.txns
+                    .iter()
+                    .map(|(txn_id, txn)| (txn_id, txn, acc.events))
+            })
+            .filter(|(_, txn, _)| txn.state == TxnState::Disputed)
+            .map(|(txn_id, txn, events)| TxnView::new(client_id, txn_id, &txn, events))
+            .collect::<Vec<_>>();
+        views.sort_by_key(|view| view.txn_id);
+        Ok(views)
+    }
+
+    fn register_observer(&mut self, observer: Arc<dyn EngineObserver>) {
+        self.observers.push(observer);
+    }
+
+    fn limit_report(&self) -> anyhow::Result<Vec<LimitReportLine>> {
+        Ok(self
+            .limit_usage
+            .iter()
+            .filter_map(|(&client_id, usage)| {
+                let (tier, _) = self.config.limits.tier(client_id)?;
+                Some(LimitReportLine::new(client_id, tier, usage))
+            })
+            .collect())
+    }
+
+    fn memory_report(&self) -> anyhow::Result<Vec<MemoryReportLine>> {
+        Ok(self
+            .accs
+            .iter()
+            .map(|(&client_id, acc)| {
+                MemoryReportLine::new(
+                    client_id,
+                    acc,
+                    self.archive.memory_bytes(client_id, acc),
+                    self.ledger.memory_bytes(client_id),
+                )
+            })
+            .collect())
+    }
+
+    fn audit(&self) -> anyhow::Result<Vec<AccountDrift>> {
+        let drifts = self
+            .accs
+            .iter()
+            .flat_map(|(&client_id, acc)| acc.audit_with_ledger(client_id, &self.ledger))
+            .collect();
+        Ok(drifts)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::util::test::add_csv_events_to_engine;
+    use crate::{
+        audit::AuditCheck,
+        fee::FeeSchedule,
+        ledger::LedgerAccount,
+        limits::{LimitsConfig, TierLimits, DEFAULT_TIER},
+        retention::RetentionConfig,
+        util::{test::add_csv_events_to_engine, to_csv_string},
+    };
     use itertools::Itertools;
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+    use std::sync::Mutex;
 
     #[test]
     fn test_deposit() {
@@ -441,4 +982,943 @@ chargeback,1,201,";
 2,-77.89,0,-77.89,true"
         );
     }
+
+    #[test]
+    fn test_trial_balance() {
+        let mut engine = InMemoryPaymentEngine::default();
+        let events_csv = "type,client,tx,amount
+deposit,1,101,100
+deposit,1,102,20
+withdrawal,1,103,30
+deposit,2,201,50
+dispute,1,102,
+dispute,1,103,
+resolve,1,103,
+dispute,2,201,
+chargeback,2,201,";
+
+        assert_eq!(
+            add_csv_events_to_engine(&mut engine, events_csv).unwrap(),
+            "client,available,held,total,locked
+1,70,20,90,false
+2,0,0,0,true"
+        );
+
+        let trial_balance = engine.trial_balance().unwrap();
+        assert!(trial_balance.is_balanced());
+        assert_eq!(
+            "account,debit,credit
+client:1:available,0,70
+client:1:held,0,20
+client:2:available,0,0
+client:2:held,0,0
+settlement,140,0
+chargeback_loss,0,50",
+            to_csv_string(&trial_balance.lines).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_audit() {
+        let mut engine = InMemoryPaymentEngine::default();
+        let events_csv = "type,client,tx,amount
+deposit,1,101,100
+deposit,1,102,20
+withdrawal,1,103,30
+deposit,2,201,50
+deposit,2,202,5
+dispute,1,102,
+dispute,1,103,
+resolve,1,103,
+dispute,2,201,
+chargeback,2,201,";
+        add_csv_events_to_engine(&mut engine, events_csv).unwrap();
+        assert!(engine.audit().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_audit_overwrite() {
+        let mut engine = InMemoryPaymentEngine::default();
+        // Note: repeated txn overwrites the original, whose net amount is retired rather than reported as drift
+        let events_csv = "type,client,tx,amount,destination
+deposit,1,101,100,
+deposit,1,101,20,
+withdrawal,1,102,30,
+withdrawal,1,102,10,
+deposit,2,201,50,
+transfer,2,101,5,1";
+        assert_eq!(
+            add_csv_events_to_engine(&mut engine, events_csv).unwrap(),
+            "client,available,held,total,locked
+1,85,0,85,false
+2,45,0,45,false"
+        );
+        assert!(engine.audit().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_audit_drift() {
+        let mut engine = InMemoryPaymentEngine::default();
+        let events_csv = "type,client,tx,amount
+deposit,1,101,100
+deposit,2,201,50";
+        add_csv_events_to_engine(&mut engine, events_csv).unwrap();
+        // Note: balance changed behind the engine's back
+        engine.accs.get_mut(&1).unwrap().available += dec!(20);
+        assert_eq!(
+            vec![
+                AccountDrift {
+                    client_id: 1,
+                    check: AuditCheck::Total,
+                    expected: dec!(100),
+                    actual: dec!(120),
+                },
+                AccountDrift {
+                    client_id: 1,
+                    check: AuditCheck::LedgerAvailable,
+                    expected: dec!(100),
+                    actual: dec!(120),
+                }
+            ],
+            engine.audit().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_redispute_policy() {
+        let events_csv = "type,client,tx,amount
+deposit,1,101,100
+dispute,1,101,
+resolve,1,101,
+dispute,1,101,";
+
+        let mut engine = InMemoryPaymentEngine::default();
+        assert_eq!(
+            add_csv_events_to_engine(&mut engine, events_csv).unwrap(),
+            "client,available,held,total,locked
+1,0,100,100,false"
+        );
+
+        let mut engine = InMemoryPaymentEngine::new(EngineConfig {
+            allow_redispute: false,
+            ..Default::default()
+        });
+        assert_eq!(
+            add_csv_events_to_engine(&mut engine, events_csv).unwrap(),
+            "client,available,held,total,locked
+1,100,0,100,false"
+        );
+        assert!(engine
+            .dispute(1, 101, None)
+            .unwrap_err()
+            .to_string()
+            .contains("Cannot re-dispute resolved transaction"));
+    }
+
+    #[test]
+    fn test_charged_back_not_resurrected() {
+        let mut engine = InMemoryPaymentEngine::default();
+        let events_csv = "type,client,tx,amount
+deposit,1,101,100
+deposit,1,102,20
+dispute,1,102,
+chargeback,1,102,";
+        add_csv_events_to_engine(&mut engine, events_csv).unwrap();
+
+        assert!(engine
+            .deposit(1, 102, dec!(20).try_into().unwrap())
+            .unwrap_err()
+            .to_string()
+            .contains("Cannot overwrite transaction in ChargedBack state"));
+        assert_eq!(
+            TxnState::ChargedBack,
+            engine.accs[&1].txns.get(&102).unwrap().state
+        );
+        assert!(engine.audit().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_dispute_limit() {
+        let mut engine = InMemoryPaymentEngine::new(EngineConfig {
+            max_disputes_per_txn: Some(1),
+            ..Default::default()
+        });
+        let events_csv = "type,client,tx,amount
+deposit,1,101,100
+dispute,1,101,
+resolve,1,101,";
+        add_csv_events_to_engine(&mut engine, events_csv).unwrap();
+
+        let err = engine.dispute(1, 101, None).unwrap_err();
+        assert_eq!(
+            Some(&EngineError::DisputeLimitExceeded(1)),
+            err.downcast_ref::<EngineError>()
+        );
+        assert_eq!(
+            1,
+            engine.transaction(1, 101).unwrap().unwrap().dispute_count
+        );
+        assert_eq!(
+            to_csv_string(&engine.snapshots().unwrap()).unwrap(),
+            "client,available,held,total,locked
+1,100,0,100,false"
+        );
+    }
+
+    #[test]
+    fn test_dispute_time_windows() {
+        let mut engine = InMemoryPaymentEngine::new(EngineConfig {
+            dispute_window_days: Some(30),
+            auto_resolve_days: Some(7),
+            ..Default::default()
+        });
+        // 86400 seconds per day
+        let events_csv = "type,client,tx,amount,timestamp
+deposit,1,101,100,0
+deposit,1,102,20,0
+deposit,3,301,5,0
+dispute,1,102,,2592000
+dispute,3,301,,2592000
+dispute,1,101,,2592001
+chargeback,3,301,,3196800
+deposit,2,201,1,3196801";
+
+        // 101 disputed too late, 301 charged back on the 7th day, 102 auto resolved past 7 days
+        assert_eq!(
+            add_csv_events_to_engine(&mut engine, events_csv).unwrap(),
+            "client,available,held,total,locked
+1,120,0,120,false
+2,1,0,1,false
+3,0,0,0,true"
+        );
+        assert_eq!(
+            TxnState::Settled,
+            engine.accs[&1].txns.get(&101).unwrap().state
+        );
+        assert_eq!(
+            TxnState::Resolved,
+            engine.accs[&1].txns.get(&102).unwrap().state
+        );
+        assert_eq!(
+            TxnState::ChargedBack,
+            engine.accs[&3].txns.get(&301).unwrap().state
+        );
+    }
+
+    #[test]
+    fn test_auto_resolve_locked_account() {
+        let mut engine = InMemoryPaymentEngine::new(EngineConfig {
+            auto_resolve_days: Some(1),
+            ..Default::default()
+        });
+        let events_csv = "type,client,tx,amount,timestamp
+deposit,1,101,100,0
+deposit,1,102,20,0
+dispute,1,101,,10
+dispute,1,102,,10
+chargeback,1,102,,20
+deposit,2,201,1,86411";
+        // 102 charged back locks the account, 101 is still auto resolved
+        assert_eq!(
+            add_csv_events_to_engine(&mut engine, events_csv).unwrap(),
+            "client,available,held,total,locked
+1,100,0,100,true
+2,1,0,1,false"
+        );
+        assert_eq!(
+            TxnState::Resolved,
+            engine.transaction(1, 101).unwrap().unwrap().state
+        );
+        assert!(engine.audit().unwrap().is_empty());
+        // Note: manual resolves remain rejected on locked accounts
+        assert!(engine.resolve(1, 101).is_err());
+    }
+
+    #[test]
+    fn test_auto_resolve_on_clock() {
+        let mut engine = InMemoryPaymentEngine::new(EngineConfig {
+            auto_resolve_days: Some(1),
+            ..Default::default()
+        });
+        let events_csv = "type,client,tx,amount,timestamp
+deposit,1,101,100,0
+dispute,1,101,,10
+deposit,2,201,1,86410";
+        assert_eq!(
+            add_csv_events_to_engine(&mut engine, events_csv).unwrap(),
+            "client,available,held,total,locked
+1,0,100,100,false
+2,1,0,1,false"
+        );
+
+        // any event moves the clock
+        engine.advance_clock(86411).unwrap();
+        assert_eq!(
+            to_csv_string(&engine.snapshots().unwrap()).unwrap(),
+            "client,available,held,total,locked
+1,100,0,100,false
+2,1,0,1,false"
+        );
+    }
+
+    #[test]
+    fn test_partial_dispute_chargeback() {
+        let mut engine = InMemoryPaymentEngine::default();
+        let events_csv = "type,client,tx,amount
+deposit,1,101,100
+dispute,1,101,30
+dispute,1,101,80
+dispute,1,101,20";
+
+        // 80 exceeds the remaining undisputed 70
+        assert_eq!(
+            add_csv_events_to_engine(&mut engine, events_csv).unwrap(),
+            "client,available,held,total,locked
+1,50,50,100,false"
+        );
+
+        let events_csv = "type,client,tx,amount
+chargeback,1,101,60
+chargeback,1,101,40";
+
+        // 60 exceeds the disputed 50, remaining 10 released on chargeback
+        assert_eq!(
+            add_csv_events_to_engine(&mut engine, events_csv).unwrap(),
+            "client,available,held,total,locked
+1,60,0,60,true"
+        );
+        assert!(engine.audit().unwrap().is_empty());
+        assert!(engine.trial_balance().unwrap().is_balanced());
+        assert_eq!(
+            dec!(40),
+            engine.ledger().balance(LedgerAccount::ChargebackLoss)
+        );
+    }
+
+    #[test]
+    fn test_partial_dispute_resolve() {
+        let mut engine = InMemoryPaymentEngine::default();
+        let events_csv = "type,client,tx,amount
+deposit,1,101,100
+withdrawal,1,102,40
+dispute,1,102,15
+resolve,1,102,
+dispute,1,102,25";
+
+        assert_eq!(
+            add_csv_events_to_engine(&mut engine, events_csv).unwrap(),
+            "client,available,held,total,locked
+1,85,-25,60,false"
+        );
+        assert!(engine.audit().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_refund() {
+        let mut engine = InMemoryPaymentEngine::default();
+        let events_csv = "type,client,tx,amount
+deposit,1,101,100
+withdrawal,1,102,60
+refund,1,102,10
+refund,1,101,10
+refund,1,102,51
+dispute,1,102,
+refund,1,102,";
+
+        // refund of deposit, exceeding amount and under dispute are rejected
+        assert_eq!(
+            add_csv_events_to_engine(&mut engine, events_csv).unwrap(),
+            "client,available,held,total,locked
+1,100,-50,50,false"
+        );
+
+        let events_csv = "type,client,tx,amount
+chargeback,1,102,
+refund,1,102,";
+
+        // charged back withdrawal cannot be refunded
+        assert_eq!(
+            add_csv_events_to_engine(&mut engine, events_csv).unwrap(),
+            "client,available,held,total,locked
+1,100,0,100,true"
+        );
+        assert!(engine.audit().unwrap().is_empty());
+        assert!(engine.trial_balance().unwrap().is_balanced());
+    }
+
+    #[test]
+    fn test_reversal() {
+        let mut engine = InMemoryPaymentEngine::default();
+        let events_csv = "type,client,tx,amount
+deposit,1,101,100
+deposit,1,102,50
+withdrawal,1,103,30
+refund,1,103,5
+reversal,1,103,
+reversal,1,102,
+dispute,1,102,
+dispute,1,101,
+reversal,1,101,";
+
+        // reversed transactions cannot be disputed, disputed cannot be reversed
+        assert_eq!(
+            add_csv_events_to_engine(&mut engine, events_csv).unwrap(),
+            "client,available,held,total,locked
+1,0,100,100,false"
+        );
+        assert_eq!(
+            TxnState::Reversed,
+            engine.accs[&1].txns.get(&102).unwrap().state
+        );
+
+        let events_csv = "type,client,tx,amount
+resolve,1,101,
+withdrawal,1,104,1
+reversal,1,101,";
+
+        // reversal of deposit requires funds
+        assert_eq!(
+            add_csv_events_to_engine(&mut engine, events_csv).unwrap(),
+            "client,available,held,total,locked
+1,99,0,99,false"
+        );
+        assert!(engine
+            .reverse(1, 101)
+            .unwrap_err()
+            .to_string()
+            .contains("Cannot reverse due to insufficient funds"));
+        assert!(engine.audit().unwrap().is_empty());
+        assert!(engine.trial_balance().unwrap().is_balanced());
+    }
+
+    #[test]
+    fn test_transfer() {
+        let mut engine = InMemoryPaymentEngine::default();
+        let events_csv = "type,client,tx,amount,destination
+deposit,1,101,100,
+transfer,1,201,30,2
+transfer,1,202,80,2
+transfer,1,203,10,1
+transfer,3,204,10,1
+transfer,2,201,5,3";
+
+        // insufficient funds, self transfers, non-existent source and overwrites are rejected
+        assert_eq!(
+            add_csv_events_to_engine(&mut engine, events_csv).unwrap(),
+            "client,available,held,total,locked
+1,70,0,70,false
+2,30,0,30,false"
+        );
+        assert!(engine
+            .transfer(1, 203, 1, dec!(10).try_into().unwrap())
+            .unwrap_err()
+            .to_string()
+            .contains("Cannot transfer to the same account"));
+
+        let events_csv = "type,client,tx,amount,destination
+dispute,2,201,,
+dispute,1,201,10,
+reversal,2,201,,";
+
+        // disputed by the source as a unit, holding funds at the destination
+        assert_eq!(
+            add_csv_events_to_engine(&mut engine, events_csv).unwrap(),
+            "client,available,held,total,locked
+1,70,0,70,false
+2,20,10,30,false"
+        );
+        assert!(engine
+            .dispute(2, 201, None)
+            .unwrap_err()
+            .to_string()
+            .contains("Cannot dispute incoming transfer, only its source can"));
+        assert!(engine.audit().unwrap().is_empty());
+        assert!(engine.trial_balance().unwrap().is_balanced());
+
+        let events_csv = "type,client,tx,amount,destination
+chargeback,1,201,,
+transfer,1,205,10,2";
+
+        // chargeback returns the funds to the source, locking it
+        assert_eq!(
+            add_csv_events_to_engine(&mut engine, events_csv).unwrap(),
+            "client,available,held,total,locked
+1,80,0,80,true
+2,20,0,20,false"
+        );
+        assert_eq!(
+            Decimal::ZERO,
+            engine.ledger.balance(LedgerAccount::ChargebackLoss)
+        );
+        assert!(engine.audit().unwrap().is_empty());
+        assert!(engine.trial_balance().unwrap().is_balanced());
+    }
+
+    #[test]
+    fn test_transfer_reversal() {
+        let mut engine = InMemoryPaymentEngine::default();
+        let events_csv = "type,client,tx,amount,destination
+deposit,1,101,100,
+transfer,1,201,60,2
+withdrawal,2,102,50,
+reversal,1,201,,
+deposit,2,103,50,
+reversal,1,201,,";
+
+        // reversal requires funds at the destination
+        assert_eq!(
+            add_csv_events_to_engine(&mut engine, events_csv).unwrap(),
+            "client,available,held,total,locked
+1,100,0,100,false
+2,0,0,0,false"
+        );
+        assert_eq!(
+            TxnState::Reversed,
+            engine.accs[&1].txns.get(&201).unwrap().state
+        );
+        assert_eq!(
+            TxnState::Reversed,
+            engine.accs[&2].txns.get(&201).unwrap().state
+        );
+        assert!(engine.audit().unwrap().is_empty());
+        assert!(engine.trial_balance().unwrap().is_balanced());
+    }
+
+    #[test]
+    fn test_fees() {
+        let mut engine = InMemoryPaymentEngine::new(EngineConfig {
+            fees: FeeSchedule {
+                withdrawal_rate: dec!(0.01),
+                chargeback_fee: dec!(15),
+            },
+            ..Default::default()
+        });
+        let events_csv = "type,client,tx,amount
+deposit,1,101,100
+withdrawal,1,102,99.5
+withdrawal,1,103,50
+deposit,2,201,10
+dispute,2,201,
+chargeback,2,201,";
+
+        // withdrawal fee must be covered by available funds, chargeback fee can take it negative
+        assert_eq!(
+            add_csv_events_to_engine(&mut engine, events_csv).unwrap(),
+            "client,available,held,total,locked
+1,49.5,0,49.5,false
+2,-15,0,-15,true"
+        );
+        assert_eq!(dec!(15.5), engine.ledger.balance(LedgerAccount::House));
+        assert_eq!(
+            to_csv_string(&engine.fee_report().unwrap()).unwrap(),
+            "client,withdrawal,chargeback,total
+1,0.5,0,0.5
+2,0,15,15"
+        );
+
+        let events_csv = "type,client,tx,amount
+reversal,1,103,";
+
+        // fees are not refunded on reversal
+        assert_eq!(
+            add_csv_events_to_engine(&mut engine, events_csv).unwrap(),
+            "client,available,held,total,locked
+1,99.5,0,99.5,false
+2,-15,0,-15,true"
+        );
+        assert!(engine.audit().unwrap().is_empty());
+        assert!(engine.trial_balance().unwrap().is_balanced());
+    }
+
+    #[test]
+    fn test_limits() {
+        let mut engine = InMemoryPaymentEngine::new(EngineConfig {
+            limits: LimitsConfig {
+                tiers: BTreeMap::from([
+                    (
+                        DEFAULT_TIER.to_owned(),
+                        TierLimits {
+                            max_deposit: Some(dec!(1000)),
+                            max_daily_withdrawal: Some(dec!(100)),
+                            ..Default::default()
+                        },
+                    ),
+                    ("vip".to_owned(), TierLimits::default()),
+                ]),
+                clients: BTreeMap::from([(2, "vip".to_owned())]),
+            },
+            ..Default::default()
+        });
+        let events_csv = "type,client,tx,amount,timestamp
+deposit,1,101,1001,0
+deposit,1,102,500,0
+withdrawal,1,103,60,0
+withdrawal,1,104,60,3600
+withdrawal,1,105,40,7200
+deposit,2,201,5000,7200
+withdrawal,2,202,500,7200
+withdrawal,1,106,60,86400";
+
+        // daily withdrawals reset on the next day, vip tier is unlimited
+        assert_eq!(
+            add_csv_events_to_engine(&mut engine, events_csv).unwrap(),
+            "client,available,held,total,locked
+1,340,0,340,false
+2,4500,0,4500,false"
+        );
+        assert!(engine
+            .withdraw(1, 107, dec!(41).try_into().unwrap())
+            .unwrap_err()
+            .to_string()
+            .contains("Cannot withdraw, exceeding daily withdrawal limit of 100"));
+        assert_eq!(
+            to_csv_string(&engine.limit_report().unwrap()).unwrap(),
+            "client,tier,daily_withdrawn,window_withdrawals,rejections
+1,default,60,2,3
+2,vip,500,1,0"
+        );
+    }
+
+    #[test]
+    fn test_transfer_limits() {
+        let mut engine = InMemoryPaymentEngine::new(EngineConfig {
+            limits: LimitsConfig {
+                tiers: BTreeMap::from([(
+                    DEFAULT_TIER.to_owned(),
+                    TierLimits {
+                        max_withdrawal: Some(dec!(50)),
+                        max_daily_withdrawal: Some(dec!(80)),
+                        ..Default::default()
+                    },
+                )]),
+                clients: BTreeMap::new(),
+            },
+            ..Default::default()
+        });
+        let events_csv = "type,client,tx,amount,destination
+deposit,1,101,500,
+transfer,1,102,60,2
+transfer,1,103,50,2
+withdrawal,1,104,40,
+transfer,1,105,30,2";
+
+        // 102 exceeds the per withdrawal limit, 104 the daily limit shared by transfers and withdrawals
+        assert_eq!(
+            add_csv_events_to_engine(&mut engine, events_csv).unwrap(),
+            "client,available,held,total,locked
+1,420,0,420,false
+2,80,0,80,false"
+        );
+        assert!(engine
+            .transfer(1, 106, 2, dec!(30).try_into().unwrap())
+            .unwrap_err()
+            .to_string()
+            .contains("Cannot transfer, exceeding daily withdrawal limit of 80"));
+        assert_eq!(
+            to_csv_string(&engine.limit_report().unwrap()).unwrap(),
+            "client,tier,daily_withdrawn,window_withdrawals,rejections
+1,default,80,2,3"
+        );
+    }
+
+    #[derive(Default)]
+    struct RecordingObserver(Mutex<Vec<Notification>>);
+
+    impl EngineObserver for RecordingObserver {
+        fn notify(&self, notification: &Notification) {
+            self.0.lock().unwrap().push(notification.clone());
+        }
+    }
+
+    #[test]
+    fn test_observer() {
+        let mut engine = InMemoryPaymentEngine::default();
+        let observer = Arc::new(RecordingObserver::default());
+        engine.register_observer(observer.clone());
+        let events_csv = "type,client,tx,amount,destination
+deposit,1,101,100,
+withdrawal,1,102,200,
+withdrawal,1,103,20,
+transfer,1,104,30,2
+dispute,1,101,,
+chargeback,1,101,,";
+        add_csv_events_to_engine(&mut engine, events_csv).unwrap();
+
+        let notifications = observer.0.lock().unwrap();
+        assert_eq!(
+            vec![
+                (NotificationKind::Deposited, 1, 101),
+                (NotificationKind::Withdrawn, 1, 103),
+                (NotificationKind::Transferred, 1, 104),
+                (NotificationKind::Transferred, 2, 104),
+                (NotificationKind::DisputeOpened, 1, 101),
+                (NotificationKind::ChargedBack, 1, 101),
+                (NotificationKind::AccountLocked, 1, 101),
+            ],
+            notifications
+                .iter()
+                .map(|n| (n.kind, n.client_id, n.txn_id))
+                .collect::<Vec<_>>()
+        );
+        let balances =
+            |snapshot: &AccountSnapshot| (snapshot.available, snapshot.held, snapshot.locked);
+        // new accounts start off from zero balances
+        assert_eq!(
+            (Decimal::ZERO, Decimal::ZERO, false),
+            balances(&notifications[0].before)
+        );
+        assert_eq!(
+            (dec!(0), dec!(0), false),
+            balances(&notifications[3].before)
+        );
+        assert_eq!(
+            (dec!(30), dec!(0), false),
+            balances(&notifications[3].after)
+        );
+        assert_eq!(
+            (dec!(50), dec!(0), false),
+            balances(&notifications[4].before)
+        );
+        assert_eq!(
+            (dec!(-50), dec!(100), false),
+            balances(&notifications[4].after)
+        );
+        assert_eq!(
+            (dec!(-50), dec!(0), true),
+            balances(&notifications[6].after)
+        );
+    }
+
+    #[test]
+    fn test_queries() {
+        let mut engine = InMemoryPaymentEngine::default();
+        let events_csv = "type,client,tx,amount,destination
+deposit,1,103,100,
+deposit,1,101,50,
+withdrawal,1,102,20,
+transfer,1,104,30,2
+dispute,1,103,40,
+dispute,1,101,,
+dispute,1,104,,";
+        add_csv_events_to_engine(&mut engine, events_csv).unwrap();
+
+        assert_eq!(
+            Some(AccountSnapshot {
+                client_id: 2,
+                available: dec!(0),
+                held: dec!(30),
+                total: dec!(30),
+                locked: false
+            }),
+            engine.account(2).unwrap()
+        );
+        assert_eq!(None, engine.account(3).unwrap());
+        assert_eq!(None, engine.transaction(2, 103).unwrap());
+        assert_eq!(
+            to_csv_string(&[engine.transaction(1, 102).unwrap().unwrap()]).unwrap(),
+            "client,tx,type,counterparty,amount,state,disputed,charged_back,refunded,fee,timestamp,disputed_at,dispute_count,dispute_age_events
+1,102,withdrawal,,20,settled,0,0,0,0,,,0,"
+        );
+        assert_eq!(
+            to_csv_string(&engine.disputed_transactions(1).unwrap()).unwrap(),
+            "client,tx,type,counterparty,amount,state,disputed,charged_back,refunded,fee,timestamp,disputed_at,dispute_count,dispute_age_events
+1,101,deposit,,50,disputed,50,0,0,0,,,1,1
+1,103,deposit,,100,disputed,40,0,0,0,,,1,2
+1,104,transfer_out,2,30,disputed,30,0,0,0,,,1,0"
+        );
+        assert_eq!(
+            vec![(104, Some(0))],
+            engine
+                .disputed_transactions(2)
+                .unwrap()
+                .iter()
+                .map(|view| (view.txn_id, view.dispute_age_events))
+                .collect::<Vec<_>>()
+        );
+        assert!(engine.disputed_transactions(3).unwrap().is_empty());
+    }
+
+    fn retention_engine(
+        max_txns_per_client: usize,
+        spill_dir: Option<&str>,
+    ) -> InMemoryPaymentEngine {
+        InMemoryPaymentEngine::new(EngineConfig {
+            retention: RetentionConfig {
+                max_txns_per_client: Some(max_txns_per_client),
+                max_age_days: None,
+                spill_dir: spill_dir
+                    .map(|dir| std::env::temp_dir().join(format!("{dir}-{}", std::process::id()))),
+            },
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_retention_dropped() {
+        let mut engine = retention_engine(2, None);
+        let events_csv = "type,client,tx,amount,destination
+deposit,1,101,10,
+deposit,1,102,20,
+dispute,1,102,,
+deposit,1,103,30,
+transfer,1,104,40,2
+deposit,2,201,5,";
+        add_csv_events_to_engine(&mut engine, events_csv).unwrap();
+
+        // 101 and 103 evicted, whilst the disputed 102 is kept
+        let err = engine.dispute(1, 101, None).unwrap_err();
+        assert_eq!(
+            Some(&EngineError::TxnEvicted(Operation::Dispute)),
+            err.downcast_ref::<EngineError>()
+        );
+        let err = engine.reverse(1, 103).unwrap_err();
+        assert_eq!(
+            Some(&EngineError::TxnEvicted(Operation::Reverse)),
+            err.downcast_ref::<EngineError>()
+        );
+        assert_eq!(None, engine.transaction(1, 101).unwrap());
+        engine.resolve(1, 102).unwrap();
+        engine.dispute(1, 104, None).unwrap();
+
+        let memory = |engine: &InMemoryPaymentEngine| {
+            engine
+                .memory_report()
+                .unwrap()
+                .into_iter()
+                .map(|line| {
+                    (
+                        line.client_id,
+                        line.retained,
+                        line.evicted,
+                        line.archive_bytes,
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        let dropped_bytes = memory(&engine)[0].3;
+        assert!(dropped_bytes > 0);
+        assert_eq!(
+            vec![(1, 2, 2, dropped_bytes), (2, 2, 0, 0)],
+            memory(&engine)
+        );
+        assert_eq!(
+            "client,available,held,total,locked
+1,20,0,20,false
+2,5,40,45,false",
+            to_csv_string(&engine.snapshots().unwrap()).unwrap()
+        );
+
+        // dropped transactions are tracked in constant space, whilst new ids are still accepted
+        engine.deposit(1, 105, dec!(1).try_into().unwrap()).unwrap();
+        engine.deposit(1, 106, dec!(1).try_into().unwrap()).unwrap();
+        assert_eq!(
+            vec![(1, 2, 4, dropped_bytes), (2, 2, 0, 0)],
+            memory(&engine)
+        );
+        assert!(engine.audit().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_retention_dropped_out_of_order() {
+        let mut engine = retention_engine(1, None);
+        let events_csv = "type,client,tx,amount
+deposit,1,100,10
+deposit,1,200,20
+deposit,1,50,30
+withdrawal,1,60,15";
+        // ids below the highest dropped one are recorded as new, not rejected as evicted
+        add_csv_events_to_engine(&mut engine, events_csv).unwrap();
+        assert_eq!(
+            "client,available,held,total,locked
+1,45,0,45,false",
+            to_csv_string(&engine.snapshots().unwrap()).unwrap()
+        );
+        assert_eq!(3, engine.memory_report().unwrap()[0].evicted);
+
+        let err = engine.dispute(1, 50, None).unwrap_err();
+        assert_eq!(
+            Some(&EngineError::TxnEvicted(Operation::Dispute)),
+            err.downcast_ref::<EngineError>()
+        );
+        engine.dispute(1, 60, None).unwrap();
+        assert!(engine.audit().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_retention_spilled() {
+        let mut engine = retention_engine(1, Some("payments-engine-spill-test"));
+        let events_csv = "type,client,tx,amount,destination
+deposit,1,101,10,
+deposit,1,102,20,
+transfer,1,103,5,2
+deposit,2,201,5,
+dispute,1,103,,
+dispute,1,101,,
+chargeback,1,101,,";
+        add_csv_events_to_engine(&mut engine, events_csv).unwrap();
+
+        // evicted transactions are restored from the spill store
+        assert_eq!(
+            TxnState::ChargedBack,
+            engine.transaction(1, 101).unwrap().unwrap().state
+        );
+        assert_eq!(
+            TxnState::Settled,
+            engine.transaction(1, 102).unwrap().unwrap().state
+        );
+        assert_eq!(
+            TxnState::Disputed,
+            engine.transaction(2, 103).unwrap().unwrap().state
+        );
+        assert_eq!(
+            "client,available,held,total,locked
+1,15,0,15,true
+2,5,5,10,false",
+            to_csv_string(&engine.snapshots().unwrap()).unwrap()
+        );
+        assert!(engine.audit().unwrap().is_empty());
+        assert!(engine.trial_balance().unwrap().is_balanced());
+
+        // spill file is removed with the engine
+        let spill_dir = engine.config.retention.spill_dir.clone().unwrap();
+        assert_eq!(1, std::fs::read_dir(&spill_dir).unwrap().count());
+        drop(engine);
+        assert_eq!(0, std::fs::read_dir(&spill_dir).unwrap().count());
+        std::fs::remove_dir(spill_dir).unwrap();
+    }
+
+    #[test]
+    fn test_retention_max_age() {
+        let mut engine = InMemoryPaymentEngine::new(EngineConfig {
+            retention: RetentionConfig {
+                max_age_days: Some(1),
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        let day = SECONDS_PER_DAY;
+        let events_csv = format!(
+            "type,client,tx,amount,timestamp
+deposit,1,101,10,0
+deposit,2,201,10,0
+dispute,2,201,,10
+deposit,1,102,10,{}
+deposit,1,103,10,{}",
+            day / 2,
+            day + day / 4
+        );
+        add_csv_events_to_engine(&mut engine, &events_csv).unwrap();
+
+        // 101 expired, 201 is disputed, 102 is within a day
+        assert_eq!(None, engine.transaction(1, 101).unwrap());
+        assert!(engine.transaction(1, 102).unwrap().is_some());
+        assert!(engine.transaction(2, 201).unwrap().is_some());
+
+        // idle clients are swept as days pass
+        engine.resolve(2, 201).unwrap();
+        engine.advance_clock(3 * day).unwrap();
+        assert!(engine.transaction(2, 201).unwrap().is_none());
+        assert!(engine.transaction(1, 103).unwrap().is_none());
+        assert!(engine.audit().unwrap().is_empty());
+    }
 }
@@ -0,0 +1,243 @@
+use crate::types::TxnEvent;
+use csv::{ByteRecord, ReaderBuilder, Trim};
+use std::{
+    collections::BTreeMap,
+    io::Read,
+    num::NonZeroUsize,
+    sync::{
+        mpsc::{self, Receiver, SyncSender},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+/// Sizing of the parsing pipeline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PipelineOptions {
+    /// Parser threads
+    pub threads: NonZeroUsize,
+    /// Records per chunk handed to a parser thread
+    pub chunk_size: NonZeroUsize,
+    /// Chunks in flight, ie. read but not yet consumed in order, bounding memory usage
+    pub buffer_chunks: NonZeroUsize,
+}
+
+impl Default for PipelineOptions {
+    fn default() -> Self {
+        let threads = thread::available_parallelism().unwrap_or(NonZeroUsize::MIN);
+        PipelineOptions {
+            threads,
+            chunk_size: NonZeroUsize::new(1024).unwrap(),
+            buffer_chunks: threads.saturating_mul(NonZeroUsize::new(4).unwrap()),
+        }
+    }
+}
+
+/// Chunk of raw records, numbered in file order.
+type RawChunk = (u64, Vec<csv::Result<ByteRecord>>);
+/// Chunk of parsed events, numbered in file order.
+type ParsedChunk = (u64, Vec<csv::Result<TxnEvent>>);
+
+/// Reads in CSV, same as `read_csv_file()`, whilst decoding records into `TxnEvent`s on a pool of threads.
+/// A single thread splits the input into chunks of raw records, parser threads decode them, and the returned
+/// `Iterator` yields events in original order, reordering chunks as they complete.
+/// Back-pressure: reading stalls once `buffer_chunks` are in flight, until the oldest is consumed.
+pub fn read_csv_parallel(
+    input: impl Read + Send + 'static,
+    options: PipelineOptions,
+) -> impl Iterator<Item = csv::Result<TxnEvent>> {
+    let (raw_tx, raw_rx) = mpsc::sync_channel::<RawChunk>(options.threads.get());
+    let (parsed_tx, parsed_rx) = mpsc::sync_channel::<ParsedChunk>(options.buffer_chunks.get());
+    // Every chunk in flight holds a permit, returned once the chunk is consumed
+    let (permit_tx, permit_rx) = mpsc::sync_channel::<()>(options.buffer_chunks.get());
+    for _ in 0..options.buffer_chunks.get() {
+        let _ = permit_tx.send(());
+    }
+
+    let mut reader = ReaderBuilder::new()
+        .has_headers(true)
+        .trim(Trim::All)
+        .from_reader(input);
+    // Note: header failures are reported as the only event, same as `read_csv_file()`
+    let (headers, header_error) = match reader.byte_headers() {
+        Ok(headers) => (Some(Arc::new(headers.clone())), None),
+        Err(err) => (None, Some(err)),
+    };
+    if headers.is_some() {
+        thread::spawn(move || split_chunks(reader, options.chunk_size.get(), raw_tx, permit_rx));
+    }
+    let raw_rx = Arc::new(Mutex::new(raw_rx));
+    for _ in 0..options.threads.get() {
+        let raw_rx = raw_rx.clone();
+        let parsed_tx = parsed_tx.clone();
+        let headers = headers.clone();
+        thread::spawn(move || parse_chunks(&raw_rx, &parsed_tx, headers.as_deref()));
+    }
+    drop(parsed_tx);
+
+    InOrder {
+        parsed_rx,
+        permit_tx,
+        pending: BTreeMap::new(),
+        next: 0,
+        current: Vec::new().into_iter(),
+    }
+    .chain(header_error.map(Err))
+}
+
+fn split_chunks<R: Read>(
+    mut reader: csv::Reader<R>,
+    chunk_size: usize,
+    raw_tx: SyncSender<RawChunk>,
+    permit_rx: Receiver<()>,
+) {
+    let mut seq = 0;
+    let mut done = false;
+    while !done {
+        let mut chunk = Vec::with_capacity(chunk_size);
+        while chunk.len() < chunk_size {
+            let mut record = ByteRecord::new();
+            match reader.read_byte_record(&mut record) {
+                Ok(true) => chunk.push(Ok(record)),
+                Ok(false) => {
+                    done = true;
+                    break;
+                }
+                Err(err) => {
+                    // Note: io errors are fatal, the rest is reported per record, same as `read_csv_file()`
+                    done = err.is_io_error();
+                    chunk.push(Err(err));
+                    if done {
+                        break;
+                    }
+                }
+            }
+        }
+        if chunk.is_empty() {
+            break;
+        }
+        // Stops once the consumer is gone
+        if permit_rx.recv().is_err() || raw_tx.send((seq, chunk)).is_err() {
+            break;
+        }
+        seq += 1;
+    }
+}
+
+fn parse_chunks(
+    raw_rx: &Mutex<Receiver<RawChunk>>,
+    parsed_tx: &SyncSender<ParsedChunk>,
+    headers: Option<&ByteRecord>,
+) {
+    loop {
+        let Ok(Ok((seq, chunk))) = raw_rx.lock().map(|rx| rx.recv()) else {
+            return;
+        };
+        let events = chunk
+            .into_iter()
+            .map(|record| record.and_then(|record| record.deserialize(headers)))
+            .collect();
+        if parsed_tx.send((seq, events)).is_err() {
+            return;
+        }
+    }
+}
+
+/// Yields parsed chunks in sequence order, buffering those completed early.
+struct InOrder {
+    parsed_rx: Receiver<ParsedChunk>,
+    permit_tx: SyncSender<()>,
+    pending: BTreeMap<u64, Vec<csv::Result<TxnEvent>>>,
+    next: u64,
+    current: std::vec::IntoIter<csv::Result<TxnEvent>>,
+}
+
+impl Iterator for InOrder {
+    type Item = csv::Result<TxnEvent>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(event) = self.current.next() {
+                return Some(event);
+            }
+            match self.pending.remove(&self.next) {
+                Some(chunk) => {
+                    self.current = chunk.into_iter();
+                    self.next += 1;
+                    let _ = self.permit_tx.send(());
+                }
+                None => {
+                    // Note: all senders gone, ie. input exhausted
+                    let (seq, chunk) = self.parsed_rx.recv().ok()?;
+                    self.pending.insert(seq, chunk);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::test::read_csv_contents;
+    use std::fmt::Write;
+
+    fn options(threads: usize, chunk_size: usize, buffer_chunks: usize) -> PipelineOptions {
+        PipelineOptions {
+            threads: NonZeroUsize::new(threads).unwrap(),
+            chunk_size: NonZeroUsize::new(chunk_size).unwrap(),
+            buffer_chunks: NonZeroUsize::new(buffer_chunks).unwrap(),
+        }
+    }
+
+    fn outcomes(events: impl Iterator<Item = csv::Result<TxnEvent>>) -> Vec<String> {
+        events
+            .map(|event| match event {
+                Ok(event) => format!("{event:?}"),
+                Err(err) => format!("error: {err}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_matches_sequential() {
+        let mut events_csv = "type, client, tx, amount, destination\n".to_owned();
+        for i in 0..5000 {
+            let _ = match i % 7 {
+                0 => writeln!(events_csv, "deposit, {}, {i}, 1.5,", i % 13),
+                1 => writeln!(events_csv, "withdrawal, {}, {i}, 0.5,", i % 13),
+                2 => writeln!(events_csv, "dispute, {}, {},,", i % 13, i - 2),
+                3 => writeln!(events_csv, "transfer, {}, {i}, 1, {}", i % 13, i % 5),
+                4 => writeln!(events_csv, "deposit, {}, {i}, -1,", i % 13),
+                5 => writeln!(events_csv, "deposit, {}, {i}", i % 13),
+                _ => writeln!(events_csv, "resolve, {}, {},,", i % 13, i - 4),
+            };
+        }
+
+        let expected = outcomes(read_csv_contents(&events_csv));
+        for (threads, chunk_size, buffer_chunks) in
+            [(1, 1, 1), (4, 7, 2), (8, 100, 16), (3, 10000, 1)]
+        {
+            let events = read_csv_parallel(
+                std::io::Cursor::new(events_csv.clone()),
+                options(threads, chunk_size, buffer_chunks),
+            );
+            assert_eq!(expected, outcomes(events));
+        }
+    }
+
+    #[test]
+    fn test_early_drop() {
+        let events_csv = "type,client,tx,amount\n".to_owned() + &"deposit,1,1,1\n".repeat(10000);
+        let mut events = read_csv_parallel(std::io::Cursor::new(events_csv), options(2, 10, 2));
+        assert!(events.next().unwrap().is_ok());
+        // threads wind down without the consumer
+        drop(events);
+    }
+
+    #[test]
+    fn test_empty() {
+        let events = read_csv_parallel(std::io::Cursor::new(""), PipelineOptions::default());
+        assert_eq!(outcomes(read_csv_contents("")), outcomes(events));
+    }
+}
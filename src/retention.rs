@@ -0,0 +1,336 @@
+use crate::{
+    account::Account,
+    config::SECONDS_PER_DAY,
+    txn::{Txn, TxnState},
+    types::{ClientId, Timestamp, TxnId},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    mem::size_of,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use tracing::warn;
+
+/// Bounds the transactions kept in memory for disputes, unbounded by default.
+/// Transactions under dispute are never evicted.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetentionConfig {
+    /// Most recent transactions retained per client, unlimited if `None`
+    pub max_txns_per_client: Option<usize>,
+    /// Transactions older than this many days are evicted, never if `None`
+    pub max_age_days: Option<u32>,
+    /// Directory of the spill store for evicted transactions, dropped if `None`
+    pub spill_dir: Option<PathBuf>,
+}
+
+impl RetentionConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.max_txns_per_client.is_some() || self.max_age_days.is_some()
+    }
+
+    pub fn max_age(&self) -> Option<Timestamp> {
+        self.max_age_days
+            .map(|days| Timestamp::from(days) * SECONDS_PER_DAY)
+    }
+}
+
+/// Location of a spilled transaction within the spill file.
+#[derive(Debug, Clone, Copy)]
+struct SpillSlot {
+    offset: u64,
+    len: u32,
+}
+
+/// Append-only file of evicted transactions, one JSON record each, removed on drop.
+#[derive(Debug)]
+struct SpillStore {
+    path: PathBuf,
+    file: File,
+}
+
+/// Distinguishes spill files of engines within the same process, eg. shards.
+static SPILL_FILES: AtomicUsize = AtomicUsize::new(0);
+
+impl SpillStore {
+    fn create(dir: &Path) -> anyhow::Result<Self> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join(format!(
+            "txns-{}-{}.spill",
+            std::process::id(),
+            SPILL_FILES.fetch_add(1, Ordering::Relaxed)
+        ));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        Ok(SpillStore { path, file })
+    }
+
+    fn write(&self, txn: &Txn) -> anyhow::Result<SpillSlot> {
+        let mut record = serde_json::to_vec(txn)?;
+        record.push(b'\n');
+        let mut file = &self.file;
+        let offset = file.seek(SeekFrom::End(0))?;
+        file.write_all(&record)?;
+        Ok(SpillSlot {
+            offset,
+            len: record.len().try_into()?,
+        })
+    }
+
+    fn read(&self, slot: SpillSlot) -> anyhow::Result<Txn> {
+        let mut record = vec![0; slot.len as usize];
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(slot.offset))?;
+        file.read_exact(&mut record)?;
+        Ok(serde_json::from_slice(&record)?)
+    }
+}
+
+impl Drop for SpillStore {
+    fn drop(&mut self) {
+        if let Err(err) = fs::remove_file(&self.path) {
+            warn!(?err, path = ?self.path, "Error removing spill file");
+        }
+    }
+}
+
+/// Outcome of looking up an evicted transaction.
+#[derive(Debug)]
+pub enum Archived {
+    /// Never evicted
+    NotFound,
+    /// Evicted and dropped, hence no longer disputable
+    Dropped,
+    Spilled(Txn),
+}
+
+/// Transactions evicted from accounts, spilled to disk or dropped.
+/// Note: the index of spilled transactions stays in memory, at a fraction of the size of a retained transaction.
+/// Dropped transactions are tracked by the highest dropped `TxnId` per client, in constant space,
+/// hence unknown ids at or below it are deemed dropped. Engines only rely on it for actions on recorded transactions,
+/// eg. disputes, as new transactions are recorded regardless, ids of a client not necessarily increasing.
+#[derive(Debug, Default)]
+pub struct TxnArchive {
+    spill: Option<SpillStore>,
+    spilled: HashMap<(ClientId, TxnId), SpillSlot>,
+    dropped: HashMap<ClientId, TxnId>,
+}
+
+/// Approximate bytes of the archive index per spilled transaction.
+const SPILLED_ENTRY_BYTES: usize = size_of::<((ClientId, TxnId), SpillSlot)>() + 1;
+/// Approximate bytes of the archive index per client with dropped transactions.
+const DROPPED_ENTRY_BYTES: usize = size_of::<(ClientId, TxnId)>() + 1;
+
+impl TxnArchive {
+    /// Evicts the oldest transactions of the account beyond the retention limits, skipping those under dispute.
+    /// Failures to spill are logged, keeping the transaction in memory.
+    pub fn enforce(
+        &mut self,
+        config: &RetentionConfig,
+        client_id: ClientId,
+        acc: &mut Account,
+        now: Option<Timestamp>,
+    ) {
+        let cutoff = config
+            .max_age()
+            .zip(now)
+            .map(|(max_age, now)| now.saturating_sub(max_age));
+        // Note: every transaction is considered at most once, as disputed ones are moved to the back
+        for _ in 0..acc.retention_order.len() {
+            let Some(&txn_id) = acc.retention_order.front() else {
+                break;
+            };
+            // Note: order may hold stale ids, of transactions restored or overwritten since
+            let Some(txn) = acc.txns.get(&txn_id) else {
+                acc.retention_order.pop_front();
+                continue;
+            };
+            let over_limit = config
+                .max_txns_per_client
+                .is_some_and(|max| acc.txns.len() > max);
+            let expired = cutoff
+                .zip(txn.timestamp)
+                .is_some_and(|(cutoff, timestamp)| timestamp < cutoff);
+            if !over_limit && !expired {
+                break;
+            }
+            acc.retention_order.pop_front();
+            if txn.state == TxnState::Disputed {
+                acc.retention_order.push_back(txn_id);
+                continue;
+            }
+            if let Err(err) = self.evict(config, client_id, txn_id, &txn) {
+                warn!(?err, client_id, txn_id, "Error evicting transaction");
+                acc.retention_order.push_front(txn_id);
+                break;
+            }
+            if let Some(txn) = acc.txns.remove(&txn_id) {
+                acc.evicted_net += txn.net_amount();
+                acc.evicted += 1;
+            }
+        }
+    }
+
+    fn evict(
+        &mut self,
+        config: &RetentionConfig,
+        client_id: ClientId,
+        txn_id: TxnId,
+        txn: &Txn,
+    ) -> anyhow::Result<()> {
+        match &config.spill_dir {
+            Some(dir) => self.spill(dir, client_id, txn_id, txn)?,
+            None => {
+                let dropped = self.dropped.entry(client_id).or_insert(txn_id);
+                *dropped = txn_id.max(*dropped);
+            }
+        }
+        Ok(())
+    }
+
+    fn spill(
+        &mut self,
+        dir: &Path,
+        client_id: ClientId,
+        txn_id: TxnId,
+        txn: &Txn,
+    ) -> anyhow::Result<()> {
+        if self.spill.is_none() {
+            self.spill = Some(SpillStore::create(dir)?);
+        }
+        if let Some(spill) = &self.spill {
+            self.spilled.insert((client_id, txn_id), spill.write(txn)?);
+        }
+        Ok(())
+    }
+
+    /// Takes the client's evicted transactions out, eg. to move them to another archive.
+    /// Note: scans the index of all spilled transactions, whilst records taken are left behind in the spill file
+    pub fn take_client(&mut self, client_id: ClientId) -> anyhow::Result<ArchivedClient> {
+        let mut spilled = vec![];
+        if let Some(spill) = &self.spill {
+            for (&(id, txn_id), &slot) in self.spilled.iter() {
+                if id == client_id {
+                    spilled.push((txn_id, spill.read(slot)?));
+                }
+            }
+        }
+        for (txn_id, _) in &spilled {
+            self.spilled.remove(&(client_id, *txn_id));
+        }
+        Ok(ArchivedClient {
+            spilled,
+            dropped: self.dropped.remove(&client_id),
+        })
+    }
+
+    /// Moves in the client's evicted transactions, as taken from another archive.
+    pub fn put_client(
+        &mut self,
+        config: &RetentionConfig,
+        client_id: ClientId,
+        archived: ArchivedClient,
+    ) -> anyhow::Result<()> {
+        for (txn_id, txn) in &archived.spilled {
+            let Some(dir) = &config.spill_dir else {
+                anyhow::bail!("Spill directory missing for spilled transaction")
+            };
+            self.spill(dir, client_id, *txn_id, txn)?;
+        }
+        if let Some(dropped) = archived.dropped {
+            self.dropped.insert(client_id, dropped);
+        }
+        Ok(())
+    }
+
+    /// Looks up an evicted transaction, without restoring it.
+    pub fn get(&self, client_id: ClientId, txn_id: TxnId) -> anyhow::Result<Archived> {
+        if let Some(slot) = self.spilled.get(&(client_id, txn_id)) {
+            return match &self.spill {
+                Some(spill) => Ok(Archived::Spilled(spill.read(*slot)?)),
+                None => anyhow::bail!("Spill store missing for spilled transaction"),
+            };
+        }
+        match self.dropped.get(&client_id) {
+            Some(&dropped) if txn_id <= dropped => Ok(Archived::Dropped),
+            _ => Ok(Archived::NotFound),
+        }
+    }
+
+    /// Approximate bytes of the archive index of the client's evicted transactions.
+    pub fn memory_bytes(&self, client_id: ClientId, acc: &Account) -> usize {
+        match self.spill {
+            // Note: with a spill store, evicted transactions are spilled, never dropped
+            Some(_) => acc.evicted as usize * SPILLED_ENTRY_BYTES,
+            None if self.dropped.contains_key(&client_id) => DROPPED_ENTRY_BYTES,
+            None => 0,
+        }
+    }
+
+    /// Moves a spilled transaction back into the account, leaving dropped ones in the archive.
+    pub fn restore(
+        &mut self,
+        client_id: ClientId,
+        txn_id: TxnId,
+        acc: &mut Account,
+    ) -> anyhow::Result<Archived> {
+        let archived = self.get(client_id, txn_id)?;
+        if let Archived::Spilled(txn) = &archived {
+            self.spilled.remove(&(client_id, txn_id));
+            acc.evicted_net -= txn.net_amount();
+            acc.evicted -= 1;
+            acc.txns.insert(txn_id, txn.clone());
+            acc.retention_order.push_back(txn_id);
+        }
+        Ok(archived)
+    }
+}
+
+/// Evicted transactions of a single client, as moved between archives.
+#[derive(Debug, Default)]
+pub struct ArchivedClient {
+    spilled: Vec<(TxnId, Txn)>,
+    dropped: Option<TxnId>,
+}
+
+/// Memory usage of a single account.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct MemoryReportLine {
+    #[serde(rename = "client")]
+    pub client_id: ClientId,
+    /// Transactions retained in memory
+    pub retained: usize,
+    /// Transactions evicted, spilled or dropped
+    pub evicted: u64,
+    /// Approximate bytes of the archive index of evicted transactions
+    pub archive_bytes: usize,
+    /// Approximate bytes of the client's ledger balances and fee totals
+    pub ledger_bytes: usize,
+    /// Approximate bytes used in total, by the account, its retained transactions, archive index and ledger
+    pub bytes: usize,
+}
+
+impl MemoryReportLine {
+    pub fn new(
+        client_id: ClientId,
+        acc: &Account,
+        archive_bytes: usize,
+        ledger_bytes: usize,
+    ) -> Self {
+        MemoryReportLine {
+            client_id,
+            retained: acc.txns.len(),
+            evicted: acc.evicted,
+            archive_bytes,
+            ledger_bytes,
+            bytes: acc.memory_bytes() + archive_bytes + ledger_bytes,
+        }
+    }
+}
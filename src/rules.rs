@@ -0,0 +1,338 @@
+use crate::{
+    account::AccountSnapshot,
+    error::EngineError,
+    payment_engine::PaymentEngine,
+    types::{ClientId, Timestamp, TxnEvent, TxnEventDetail, TxnId},
+};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::{
+    any::Any,
+    collections::{BTreeSet, HashMap, VecDeque},
+};
+use tracing::warn;
+
+/// Verdict of a rule on a single event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RuleOutcome {
+    Accept,
+    /// Event is processed, but reported for review
+    Flag(String),
+    /// Event is not processed
+    Reject(String),
+}
+
+/// Pre-processing check of events, prior to reaching the engine.
+pub trait Rule: Send {
+    fn name(&self) -> &str;
+
+    /// Screens the event, given the account state prior to the event, if the account exists.
+    fn check(&mut self, event: &TxnEvent, account: Option<&AccountSnapshot>) -> RuleOutcome;
+
+    /// Takes the state kept for the client out, if any, eg. as the client moves to another shard.
+    fn take_client_state(&mut self, _client_id: ClientId) -> Option<Box<dyn Any + Send>> {
+        None
+    }
+
+    /// Moves in the state kept for the client, as taken from a rule of the same type.
+    fn put_client_state(&mut self, _client_id: ClientId, _state: Box<dyn Any + Send>) {}
+}
+
+/// Event flagged by a rule.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct RuleFlag {
+    #[serde(rename = "client")]
+    pub client_id: ClientId,
+    #[serde(rename = "tx")]
+    pub txn_id: TxnId,
+    pub rule: String,
+    pub reason: String,
+}
+
+/// Ordered chain of rules, evaluated until the first rejection.
+#[derive(Default)]
+pub struct RuleChain {
+    rules: Vec<Box<dyn Rule>>,
+    flags: Vec<RuleFlag>,
+}
+
+impl RuleChain {
+    /// Builds the chain of built-in rules enabled in the config.
+    pub fn from_config(config: &RulesConfig) -> Self {
+        let mut chain = RuleChain::default();
+        if !config.block_list.is_empty() {
+            chain.push(BlockList {
+                clients: config.block_list.clone(),
+            });
+        }
+        if let Some(thresholds) = &config.amount_thresholds {
+            chain.push(thresholds.clone());
+        }
+        if let Some(rapid_disputes) = &config.rapid_disputes {
+            chain.push(RapidDisputes::new(rapid_disputes.clone()));
+        }
+        chain
+    }
+
+    pub fn push(&mut self, rule: impl Rule + 'static) {
+        self.rules.push(Box::new(rule));
+    }
+
+    /// Events flagged so far.
+    pub fn flags(&self) -> &[RuleFlag] {
+        &self.flags
+    }
+
+    /// Drains the events flagged so far.
+    pub fn take_flags(&mut self) -> Vec<RuleFlag> {
+        std::mem::take(&mut self.flags)
+    }
+
+    /// Moves the state kept for the client to the other chain, built from the same config.
+    pub fn move_client(&mut self, client_id: ClientId, other: &mut RuleChain) {
+        for (rule, other_rule) in self.rules.iter_mut().zip(other.rules.iter_mut()) {
+            if let Some(state) = rule.take_client_state(client_id) {
+                other_rule.put_client_state(client_id, state);
+            }
+        }
+    }
+
+    /// Screens the event, rejecting it with `EngineError::RuleRejected`, or passing it on to the engine.
+    pub fn add_event(
+        &mut self,
+        engine: &mut dyn PaymentEngine,
+        event: TxnEvent,
+    ) -> anyhow::Result<()> {
+        let account = engine.account(event.client_id)?;
+        for rule in self.rules.iter_mut() {
+            match rule.check(&event, account.as_ref()) {
+                RuleOutcome::Accept => (),
+                RuleOutcome::Flag(reason) => {
+                    warn!(rule = rule.name(), reason, ?event, "Event flagged");
+                    self.flags.push(RuleFlag {
+                        client_id: event.client_id,
+                        txn_id: event.txn_id,
+                        rule: rule.name().to_owned(),
+                        reason,
+                    });
+                }
+                RuleOutcome::Reject(reason) => anyhow::bail!(EngineError::RuleRejected {
+                    rule: rule.name().to_owned(),
+                    reason
+                }),
+            }
+        }
+        engine.add_event(event)
+    }
+}
+
+/// Built-in rules, all disabled by default.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RulesConfig {
+    /// Clients whose events are all rejected
+    pub block_list: BTreeSet<ClientId>,
+    pub amount_thresholds: Option<AmountThresholds>,
+    pub rapid_disputes: Option<RapidDisputesConfig>,
+}
+
+/// Rejects events of blocked clients.
+pub struct BlockList {
+    pub clients: BTreeSet<ClientId>,
+}
+
+impl Rule for BlockList {
+    fn name(&self) -> &str {
+        "block_list"
+    }
+
+    fn check(&mut self, event: &TxnEvent, _account: Option<&AccountSnapshot>) -> RuleOutcome {
+        if self.clients.contains(&event.client_id) {
+            RuleOutcome::Reject(format!("client {} is blocked", event.client_id))
+        } else {
+            RuleOutcome::Accept
+        }
+    }
+}
+
+/// Flags or rejects deposits, withdrawals and transfers above the thresholds.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AmountThresholds {
+    pub flag_above: Option<Decimal>,
+    pub reject_above: Option<Decimal>,
+}
+
+impl Rule for AmountThresholds {
+    fn name(&self) -> &str {
+        "amount_thresholds"
+    }
+
+    fn check(&mut self, event: &TxnEvent, _account: Option<&AccountSnapshot>) -> RuleOutcome {
+        let amount = match &event.detail {
+            TxnEventDetail::Deposit { amount }
+            | TxnEventDetail::Withdrawal { amount }
+            | TxnEventDetail::Transfer { amount, .. } => **amount,
+            _ => return RuleOutcome::Accept,
+        };
+        match (self.reject_above, self.flag_above) {
+            (Some(limit), _) if amount > limit => {
+                RuleOutcome::Reject(format!("amount {amount} above {limit}"))
+            }
+            (_, Some(limit)) if amount > limit => {
+                RuleOutcome::Flag(format!("amount {amount} above {limit}"))
+            }
+            _ => RuleOutcome::Accept,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RapidDisputesConfig {
+    /// Disputes per client allowed within the window, further disputes are flagged
+    pub max_disputes: u32,
+    pub window_hours: u32,
+}
+
+/// Flags clients raising disputes in quick succession, as per event time.
+pub struct RapidDisputes {
+    config: RapidDisputesConfig,
+    recent_disputes: HashMap<ClientId, VecDeque<Timestamp>>,
+}
+
+impl RapidDisputes {
+    pub fn new(config: RapidDisputesConfig) -> Self {
+        RapidDisputes {
+            config,
+            recent_disputes: HashMap::new(),
+        }
+    }
+}
+
+impl Rule for RapidDisputes {
+    fn name(&self) -> &str {
+        "rapid_disputes"
+    }
+
+    fn check(&mut self, event: &TxnEvent, _account: Option<&AccountSnapshot>) -> RuleOutcome {
+        if !matches!(event.detail, TxnEventDetail::Dispute { .. }) {
+            return RuleOutcome::Accept;
+        }
+        // Note: without timestamps, all disputes fall within the same window
+        let now = event.timestamp.unwrap_or_default();
+        let window = Timestamp::from(self.config.window_hours) * 60 * 60;
+        let recent = self.recent_disputes.entry(event.client_id).or_default();
+        while recent
+            .front()
+            .is_some_and(|&at| at.saturating_add(window) <= now)
+        {
+            recent.pop_front();
+        }
+        recent.push_back(now);
+        if recent.len() > self.config.max_disputes as usize {
+            RuleOutcome::Flag(format!(
+                "{} disputes within {} hour(s)",
+                recent.len(),
+                self.config.window_hours
+            ))
+        } else {
+            RuleOutcome::Accept
+        }
+    }
+
+    fn take_client_state(&mut self, client_id: ClientId) -> Option<Box<dyn Any + Send>> {
+        let recent = self.recent_disputes.remove(&client_id)?;
+        Some(Box::new(recent))
+    }
+
+    fn put_client_state(&mut self, client_id: ClientId, state: Box<dyn Any + Send>) {
+        if let Ok(recent) = state.downcast::<VecDeque<Timestamp>>() {
+            self.recent_disputes.insert(client_id, *recent);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        payment_engine::InMemoryPaymentEngine,
+        util::{test::read_csv_contents, to_csv_string},
+    };
+    use rust_decimal_macros::dec;
+
+    /// Rejects withdrawals of more than half the available funds.
+    struct HalfAvailable;
+
+    impl Rule for HalfAvailable {
+        fn name(&self) -> &str {
+            "half_available"
+        }
+
+        fn check(&mut self, event: &TxnEvent, account: Option<&AccountSnapshot>) -> RuleOutcome {
+            match (&event.detail, account) {
+                (TxnEventDetail::Withdrawal { amount }, Some(acc))
+                    if **amount * dec!(2) > acc.available =>
+                {
+                    RuleOutcome::Reject("more than half of available".to_owned())
+                }
+                _ => RuleOutcome::Accept,
+            }
+        }
+    }
+
+    #[test]
+    fn test_rule_chain() -> anyhow::Result<()> {
+        let mut chain = RuleChain::from_config(&RulesConfig {
+            block_list: BTreeSet::from([3]),
+            amount_thresholds: Some(AmountThresholds {
+                flag_above: Some(dec!(500)),
+                reject_above: Some(dec!(1000)),
+            }),
+            rapid_disputes: Some(RapidDisputesConfig {
+                max_disputes: 1,
+                window_hours: 1,
+            }),
+        });
+        chain.push(HalfAvailable);
+        let mut engine = InMemoryPaymentEngine::default();
+        let events_csv = "type,client,tx,amount,timestamp
+deposit,1,101,600,0
+deposit,1,102,1001,0
+deposit,2,201,100,0
+deposit,3,301,100,0
+withdrawal,1,103,301,0
+withdrawal,1,104,300,0
+dispute,2,201,,60
+resolve,2,201,,120
+dispute,2,201,,180
+resolve,2,201,,240
+dispute,2,201,,3780";
+        let errs = read_csv_contents(events_csv)
+            .map(|event| chain.add_event(&mut engine, event?))
+            .filter_map(|res| res.err().map(|err| err.to_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                "Rule amount_thresholds rejected event: amount 1001 above 1000",
+                "Rule block_list rejected event: client 3 is blocked",
+                "Rule half_available rejected event: more than half of available",
+            ],
+            errs
+        );
+        assert_eq!(
+            to_csv_string(&engine.snapshots()?)?,
+            "client,available,held,total,locked
+1,300,0,300,false
+2,0,100,100,false"
+        );
+        assert_eq!(
+            to_csv_string(chain.flags())?,
+            "client,tx,rule,reason
+1,101,amount_thresholds,amount 600 above 500
+2,201,rapid_disputes,2 disputes within 1 hour(s)"
+        );
+        Ok(())
+    }
+}
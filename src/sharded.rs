@@ -0,0 +1,609 @@
+use crate::{
+    account::AccountSnapshot,
+    audit::AccountDrift,
+    config::EngineConfig,
+    decimal::PositiveDecimal,
+    fee::FeeReportLine,
+    ingest::{ingest_into, BatchSummary},
+    ledger::TrialBalance,
+    limits::LimitReportLine,
+    observer::EngineObserver,
+    payment_engine::{InMemoryPaymentEngine, PaymentEngine},
+    retention::MemoryReportLine,
+    rules::RuleChain,
+    txn::TxnView,
+    types::{ClientId, Timestamp, TxnEvent, TxnEventDetail, TxnId},
+};
+use std::{collections::HashMap, sync::mpsc, sync::Arc, thread, time::Instant};
+use tracing::warn;
+
+/// Bound of events queued per shard, back-pressuring the reader.
+const SHARD_QUEUE_SIZE: usize = 1024;
+
+/// Routes clients to shards by `client % shards`, unless moved to the shard of a transfer counterparty.
+/// Clients linked by transfers, directly or not, form a group kept on a single shard,
+/// as legs of a transfer are acted upon as a single unit, eg. by disputes.
+/// Note: memory grows with clients party to transfers
+#[derive(Debug)]
+struct Router {
+    shard_count: usize,
+    /// Shards of clients moved away from their default shard
+    moved: HashMap<ClientId, usize>,
+    /// Group of each client party to a transfer, keyed by the group's first client
+    groups: HashMap<ClientId, ClientId>,
+    members: HashMap<ClientId, Vec<ClientId>>,
+}
+
+/// Clients to move from one shard to another.
+#[derive(Debug)]
+struct Move {
+    clients: Vec<ClientId>,
+    from: usize,
+    to: usize,
+}
+
+impl Router {
+    fn new(shard_count: usize) -> Self {
+        Router {
+            shard_count,
+            moved: HashMap::new(),
+            groups: HashMap::new(),
+            members: HashMap::new(),
+        }
+    }
+
+    fn shard(&self, client_id: ClientId) -> usize {
+        self.moved
+            .get(&client_id)
+            .copied()
+            .unwrap_or(client_id as usize % self.shard_count)
+    }
+
+    fn group(&mut self, client_id: ClientId) -> ClientId {
+        *self.groups.entry(client_id).or_insert_with(|| {
+            self.members.insert(client_id, vec![client_id]);
+            client_id
+        })
+    }
+
+    /// Links the parties to a transfer into one group, the smaller group joining the larger one,
+    /// returning the clients to move if the groups were on different shards.
+    fn link(&mut self, client_id: ClientId, destination_client_id: ClientId) -> Option<Move> {
+        let (mut group, mut other) = (self.group(client_id), self.group(destination_client_id));
+        if group == other {
+            return None;
+        }
+        if self.members[&group].len() < self.members[&other].len() {
+            (group, other) = (other, group);
+        }
+        let (from, to) = (self.shard(other), self.shard(group));
+        let clients = self.members.remove(&other).unwrap_or_default();
+        for &client_id in &clients {
+            self.groups.insert(client_id, group);
+            if client_id as usize % self.shard_count == to {
+                self.moved.remove(&client_id);
+            } else {
+                self.moved.insert(client_id, to);
+            }
+        }
+        self.members
+            .entry(group)
+            .or_default()
+            .extend(clients.iter().copied());
+        (from != to).then_some(Move { clients, from, to })
+    }
+}
+
+/// Engine partitioning accounts by `ClientId` into independent `InMemoryPaymentEngine` shards,
+/// allowing for batches to be processed in parallel, one thread per shard.
+/// Transfers across shards move the parties onto the same shard, see `Router`.
+pub struct ShardedPaymentEngine {
+    shards: Vec<InMemoryPaymentEngine>,
+    router: Router,
+}
+
+impl ShardedPaymentEngine {
+    pub fn new(shard_count: usize, config: EngineConfig) -> Self {
+        assert!(shard_count > 0, "At least 1 shard required");
+        ShardedPaymentEngine {
+            shards: (0..shard_count)
+                .map(|_| InMemoryPaymentEngine::new(config.clone()))
+                .collect(),
+            router: Router::new(shard_count),
+        }
+    }
+
+    fn shard(&self, client_id: ClientId) -> &InMemoryPaymentEngine {
+        &self.shards[self.router.shard(client_id)]
+    }
+
+    fn shard_mut(&mut self, client_id: ClientId) -> &mut InMemoryPaymentEngine {
+        let index = self.router.shard(client_id);
+        &mut self.shards[index]
+    }
+
+    /// Moves all state of the clients between shards.
+    fn move_clients(&mut self, m: &Move) -> anyhow::Result<()> {
+        for &client_id in &m.clients {
+            let state = self.shards[m.from].take_client(client_id)?;
+            self.shards[m.to].put_client(client_id, state)?;
+        }
+        Ok(())
+    }
+
+    /// Processes the events in parallel, routing events to shards by client, preserving per client ordering.
+    /// Each shard screens its events via its own chain of rules, as created by `rules`.
+    /// Parse errors are rejected upfront, prior to routing.
+    /// Transfers across shards pause all shards, until the parties are moved onto the same shard, along with their
+    /// rules' state, hence are costly, though only once per pair of groups of clients linked by transfers.
+    /// Note: outcomes match sequential processing, provided timestamps, if any, are in order.
+    pub fn ingest_parallel(
+        &mut self,
+        events: impl Iterator<Item = csv::Result<TxnEvent>>,
+        rules: impl Fn() -> RuleChain,
+    ) -> anyhow::Result<BatchSummary> {
+        let start = Instant::now();
+        let mut events = events;
+        let mut chains = self.shards.iter().map(|_| rules()).collect::<Vec<_>>();
+        let mut summaries = vec![BatchSummary::default(); self.shards.len()];
+        let mut latest = None;
+        let mut summary = BatchSummary::default();
+        // Note: transfer across shards, routed once its parties are moved
+        let mut pending = None;
+        loop {
+            let Self { shards, router } = self;
+            let moving = thread::scope(|scope| {
+                let (senders, workers): (Vec<_>, Vec<_>) = shards
+                    .iter_mut()
+                    .zip(chains.iter_mut())
+                    .zip(summaries.iter_mut())
+                    .map(|((shard, rules), summary)| {
+                        let (tx, rx) = mpsc::sync_channel(SHARD_QUEUE_SIZE);
+                        let worker = scope.spawn(move || {
+                            ingest_into(rx.into_iter().map(Ok), rules, shard, summary)
+                        });
+                        (tx, worker)
+                    })
+                    .unzip();
+
+                let mut moving = None;
+                for event in pending.take().map(Ok).into_iter().chain(events.by_ref()) {
+                    let event = match event {
+                        Ok(event) => event,
+                        Err(err) => {
+                            warn!(?err, "Error reading event"); // Note: skipping errors
+                            summary.record_parse_error();
+                            continue;
+                        }
+                    };
+                    if let TxnEventDetail::Transfer {
+                        destination_client_id,
+                        ..
+                    } = event.detail
+                    {
+                        if let Some(m) = router.link(event.client_id, destination_client_id) {
+                            moving = Some((m, event));
+                            break;
+                        }
+                    }
+                    latest = latest.max(event.timestamp);
+                    // Note: a shard can only hang up on failure, reported upon join
+                    if senders[router.shard(event.client_id)].send(event).is_err() {
+                        break;
+                    }
+                }
+                drop(senders);
+
+                for worker in workers {
+                    worker
+                        .join()
+                        .unwrap_or_else(|err| std::panic::resume_unwind(err));
+                }
+                moving
+            });
+            let Some((m, event)) = moving else {
+                break;
+            };
+            // Shard clocks only move with their own events, catch up prior to moving clients between them
+            if let Some(latest) = latest {
+                self.advance_clock(latest)?;
+            }
+            self.move_clients(&m)?;
+            for &client_id in &m.clients {
+                let (from, to) = if m.from < m.to {
+                    let (left, right) = chains.split_at_mut(m.to);
+                    (&mut left[m.from], &mut right[0])
+                } else {
+                    let (left, right) = chains.split_at_mut(m.from);
+                    (&mut right[0], &mut left[m.to])
+                };
+                from.move_client(client_id, to);
+            }
+            pending = Some(event);
+        }
+
+        // Shard clocks only move with their own events, catch up with the latest event time overall
+        if let Some(latest) = latest {
+            self.advance_clock(latest)?;
+        }
+        for shard_summary in summaries {
+            summary.merge(shard_summary);
+        }
+        summary.finish(self)?;
+        summary.elapsed_secs = start.elapsed().as_secs_f64();
+        summary.update_throughput();
+        Ok(summary)
+    }
+}
+
+impl PaymentEngine for ShardedPaymentEngine {
+    fn deposit(
+        &mut self,
+        client_id: ClientId,
+        txn_id: TxnId,
+        amount: PositiveDecimal,
+    ) -> anyhow::Result<()> {
+        self.shard_mut(client_id).deposit(client_id, txn_id, amount)
+    }
+
+    fn withdraw(
+        &mut self,
+        client_id: ClientId,
+        txn_id: TxnId,
+        amount: PositiveDecimal,
+    ) -> anyhow::Result<()> {
+        self.shard_mut(client_id)
+            .withdraw(client_id, txn_id, amount)
+    }
+
+    fn dispute(
+        &mut self,
+        client_id: ClientId,
+        txn_id: TxnId,
+        amount: Option<PositiveDecimal>,
+    ) -> anyhow::Result<()> {
+        self.shard_mut(client_id).dispute(client_id, txn_id, amount)
+    }
+
+    fn resolve(&mut self, client_id: ClientId, txn_id: TxnId) -> anyhow::Result<()> {
+        self.shard_mut(client_id).resolve(client_id, txn_id)
+    }
+
+    fn chargeback(
+        &mut self,
+        client_id: ClientId,
+        txn_id: TxnId,
+        amount: Option<PositiveDecimal>,
+    ) -> anyhow::Result<()> {
+        self.shard_mut(client_id)
+            .chargeback(client_id, txn_id, amount)
+    }
+
+    /// Transfers within the shard, moving the parties onto the same shard first if needed.
+    fn transfer(
+        &mut self,
+        client_id: ClientId,
+        txn_id: TxnId,
+        destination_client_id: ClientId,
+        amount: PositiveDecimal,
+    ) -> anyhow::Result<()> {
+        if let Some(m) = self.router.link(client_id, destination_client_id) {
+            self.move_clients(&m)?;
+        }
+        self.shard_mut(client_id)
+            .transfer(client_id, txn_id, destination_client_id, amount)
+    }
+
+    fn refund(
+        &mut self,
+        client_id: ClientId,
+        txn_id: TxnId,
+        amount: Option<PositiveDecimal>,
+    ) -> anyhow::Result<()> {
+        self.shard_mut(client_id).refund(client_id, txn_id, amount)
+    }
+
+    fn reverse(&mut self, client_id: ClientId, txn_id: TxnId) -> anyhow::Result<()> {
+        self.shard_mut(client_id).reverse(client_id, txn_id)
+    }
+
+    /// Advances clocks of all shards, as time based policies apply to all accounts.
+    fn advance_clock(&mut self, timestamp: Timestamp) -> anyhow::Result<()> {
+        for shard in self.shards.iter_mut() {
+            shard.advance_clock(timestamp)?;
+        }
+        Ok(())
+    }
+
+    fn now(&self) -> Option<Timestamp> {
+        self.shards.iter().filter_map(|shard| shard.now()).max()
+    }
+
+    fn snapshots(&self) -> anyhow::Result<Vec<AccountSnapshot>> {
+        let mut snapshots = vec![];
+        for shard in &self.shards {
+            snapshots.extend(shard.snapshots()?);
+        }
+        snapshots.sort_by_key(|snapshot| snapshot.client_id);
+        Ok(snapshots)
+    }
+
+    fn account(&self, client_id: ClientId) -> anyhow::Result<Option<AccountSnapshot>> {
+        self.shard(client_id).account(client_id)
+    }
+
+    fn transaction(&self, client_id: ClientId, txn_id: TxnId) -> anyhow::Result<Option<TxnView>> {
+        self.shard(client_id).transaction(client_id, txn_id)
+    }
+
+    fn disputed_transactions(&self, client_id: ClientId) -> anyhow::Result<Vec<TxnView>> {
+        self.shard(client_id).disputed_transactions(client_id)
+    }
+
+    /// Combined trial balance, merging house accounts across shards.
+    fn trial_balance(&self) -> anyhow::Result<TrialBalance> {
+        let mut balances = vec![];
+        for shard in &self.shards {
+            balances.extend(
+                shard
+                    .trial_balance()?
+                    .lines
+                    .into_iter()
+                    .map(|line| (line.account, line.credit - line.debit)),
+            );
+        }
+        Ok(TrialBalance::from_balances(balances))
+    }
+
+    fn audit(&self) -> anyhow::Result<Vec<AccountDrift>> {
+        let mut drifts = vec![];
+        for shard in &self.shards {
+            drifts.extend(shard.audit()?);
+        }
+        drifts.sort_by_key(|drift| drift.client_id);
+        Ok(drifts)
+    }
+
+    fn fee_report(&self) -> anyhow::Result<Vec<FeeReportLine>> {
+        let mut lines = vec![];
+        for shard in &self.shards {
+            lines.extend(shard.fee_report()?);
+        }
+        lines.sort_by_key(|line| line.client_id);
+        Ok(lines)
+    }
+
+    fn limit_report(&self) -> anyhow::Result<Vec<LimitReportLine>> {
+        let mut lines = vec![];
+        for shard in &self.shards {
+            lines.extend(shard.limit_report()?);
+        }
+        lines.sort_by_key(|line| line.client_id);
+        Ok(lines)
+    }
+
+    fn memory_report(&self) -> anyhow::Result<Vec<MemoryReportLine>> {
+        let mut lines = vec![];
+        for shard in &self.shards {
+            lines.extend(shard.memory_report()?);
+        }
+        lines.sort_by_key(|line| line.client_id);
+        Ok(lines)
+    }
+
+    fn register_observer(&mut self, observer: Arc<dyn EngineObserver>) {
+        for shard in self.shards.iter_mut() {
+            shard.register_observer(observer.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fee::FeeSchedule,
+        ingest::ingest,
+        retention::RetentionConfig,
+        rules::{AmountThresholds, RapidDisputesConfig, RulesConfig},
+        util::{
+            test::{add_csv_events_to_engine, read_csv_contents},
+            to_csv_string,
+        },
+    };
+    use rust_decimal_macros::dec;
+
+    /// Feed of deposits, withdrawals, disputes and chargebacks across many clients.
+    fn generate_feed(clients: u16, txns_per_client: u32) -> String {
+        let mut lines = vec!["type,client,tx,amount,timestamp".to_owned()];
+        for i in 0..txns_per_client {
+            for client_id in 0..clients {
+                let txn_id = i * u32::from(clients) + u32::from(client_id);
+                let line = match (i + u32::from(client_id)) % 7 {
+                    _ if i < 2 => format!("deposit,{client_id},{txn_id},{}.5,", i + 10),
+                    0..=2 => format!("deposit,{client_id},{txn_id},{}.5,", i + 10),
+                    3 | 4 => format!("withdrawal,{client_id},{txn_id},{},", i + 3),
+                    5 => format!("dispute,{client_id},{},,", txn_id - u32::from(clients)),
+                    _ if client_id % 5 == 0 => {
+                        format!(
+                            "chargeback,{client_id},{},,",
+                            txn_id - 2 * u32::from(clients)
+                        )
+                    }
+                    _ => format!("resolve,{client_id},{},,", txn_id - 2 * u32::from(clients)),
+                };
+                lines.push(line);
+            }
+        }
+        lines.join("\n")
+    }
+
+    #[test]
+    fn test_matches_sequential() -> anyhow::Result<()> {
+        let feed = generate_feed(50, 100);
+
+        let mut engine = InMemoryPaymentEngine::default();
+        let sequential = ingest(
+            read_csv_contents(&feed),
+            &mut RuleChain::default(),
+            &mut engine,
+        )?;
+
+        let mut sharded = ShardedPaymentEngine::new(4, EngineConfig::default());
+        let parallel = sharded.ingest_parallel(read_csv_contents(&feed), RuleChain::default)?;
+
+        assert_eq!(
+            to_csv_string(&engine.snapshots()?)?,
+            to_csv_string(&sharded.snapshots()?)?
+        );
+        assert_eq!(engine.trial_balance()?, sharded.trial_balance()?);
+        assert!(sharded.audit()?.is_empty());
+        assert!(parallel.locked_accounts > 0);
+        assert_eq!(sequential.events_by_type, parallel.events_by_type);
+        assert_eq!(sequential.rejected_by_reason, parallel.rejected_by_reason);
+        assert_eq!(
+            (
+                sequential.deposited,
+                sequential.held,
+                sequential.locked_accounts
+            ),
+            (parallel.deposited, parallel.held, parallel.locked_accounts)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_flags_merged() -> anyhow::Result<()> {
+        let config = RulesConfig {
+            amount_thresholds: Some(AmountThresholds {
+                flag_above: Some(dec!(50)),
+                reject_above: None,
+            }),
+            ..Default::default()
+        };
+        let mut sharded = ShardedPaymentEngine::new(2, EngineConfig::default());
+        let events_csv = "type,client,tx,amount
+deposit,1,101,100
+deposit,2,201,60
+deposit,3,301,10
+withdrawal,1,102,70";
+        let summary = sharded.ingest_parallel(read_csv_contents(events_csv), || {
+            RuleChain::from_config(&config)
+        })?;
+
+        assert_eq!(3, summary.flagged);
+        let mut flagged = summary
+            .flags
+            .iter()
+            .map(|flag| (flag.client_id, flag.txn_id))
+            .collect::<Vec<_>>();
+        flagged.sort();
+        assert_eq!(vec![(1, 101), (1, 102), (2, 201)], flagged);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cross_shard_transfers_match_sequential() -> anyhow::Result<()> {
+        let clients = 20_u32;
+        let mut lines = vec!["type,client,tx,amount,destination,timestamp".to_owned()];
+        for i in 0..30_u32 {
+            for client_id in 0..clients {
+                let txn_id = i * clients + client_id;
+                let timestamp = txn_id * 600;
+                let line = match (i + client_id) % 6 {
+                    _ if i < 2 => format!("deposit,{client_id},{txn_id},100,"),
+                    0 => format!("deposit,{client_id},{txn_id},20.5,"),
+                    1 => {
+                        let destination = (client_id * 7 + i) % clients;
+                        format!("transfer,{client_id},{txn_id},5,{destination}")
+                    }
+                    2 | 5 => format!("dispute,{client_id},{},,", txn_id - clients),
+                    3 if client_id % 5 == 0 => {
+                        format!("chargeback,{client_id},{},,", txn_id - 2 * clients)
+                    }
+                    3 => format!("resolve,{client_id},{},,", txn_id - 2 * clients),
+                    _ => format!("withdrawal,{client_id},{txn_id},3,"),
+                };
+                lines.push(format!("{line},{timestamp}"));
+            }
+        }
+        let feed = lines.join("\n");
+        let config = EngineConfig {
+            auto_resolve_days: Some(1),
+            fees: FeeSchedule {
+                withdrawal_rate: dec!(0.01),
+                chargeback_fee: dec!(2),
+            },
+            retention: RetentionConfig {
+                max_txns_per_client: Some(4),
+                max_age_days: None,
+                spill_dir: Some(std::env::temp_dir().join(format!(
+                    "payments-engine-sharded-test-{}",
+                    std::process::id()
+                ))),
+            },
+            rules: RulesConfig {
+                rapid_disputes: Some(RapidDisputesConfig {
+                    max_disputes: 1,
+                    window_hours: 24,
+                }),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let mut engine = InMemoryPaymentEngine::new(config.clone());
+        let sequential = ingest(
+            read_csv_contents(&feed),
+            &mut RuleChain::from_config(&config.rules),
+            &mut engine,
+        )?;
+        let mut sharded = ShardedPaymentEngine::new(4, config.clone());
+        let parallel = sharded.ingest_parallel(read_csv_contents(&feed), || {
+            RuleChain::from_config(&config.rules)
+        })?;
+
+        assert!(sequential.events_by_type["transfer"] > 0);
+        assert_eq!(
+            to_csv_string(&engine.snapshots()?)?,
+            to_csv_string(&sharded.snapshots()?)?
+        );
+        assert_eq!(engine.trial_balance()?, sharded.trial_balance()?);
+        assert_eq!(engine.fee_report()?, sharded.fee_report()?);
+        assert!(sharded.audit()?.is_empty());
+        assert_eq!(sequential.rejected_by_reason, parallel.rejected_by_reason);
+        assert_eq!(sequential.flagged_by_rule, parallel.flagged_by_rule);
+        assert!(parallel.flagged > 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cross_shard_transfer() -> anyhow::Result<()> {
+        let mut sharded = ShardedPaymentEngine::new(2, EngineConfig::default());
+        let events_csv = "type,client,tx,amount,destination
+deposit,1,101,100,
+transfer,1,102,10,3
+transfer,1,103,10,2
+dispute,1,103,,
+deposit,4,401,10,
+transfer,4,402,10,2";
+        add_csv_events_to_engine(&mut sharded, events_csv)?;
+
+        // clients linked by transfers share a shard, the smaller group joining the larger one
+        assert_eq!(
+            vec![1, 1, 1, 1],
+            [1, 2, 3, 4].map(|client_id| sharded.router.shard(client_id))
+        );
+        assert_eq!(
+            to_csv_string(&sharded.snapshots()?)?,
+            "client,available,held,total,locked
+1,80,0,80,false
+2,10,10,20,false
+3,10,0,10,false
+4,0,0,0,false"
+        );
+        assert!(sharded.trial_balance()?.is_balanced());
+        assert!(sharded.audit()?.is_empty());
+        Ok(())
+    }
+}
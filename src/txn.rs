@@ -1,23 +1,566 @@
+use crate::{
+    account::serialize_decimal_4_places,
+    config::EngineConfig,
+    error::{EngineError, Operation},
+    ledger::serialize_display,
+    types::{ClientId, Timestamp, TxnId},
+};
 use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::fmt;
 
-#[derive(Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TxnType {
     Deposit,
     Withdrawal,
+    /// Source leg of a transfer
+    TransferOut {
+        counterparty: ClientId,
+    },
+    /// Destination leg of a transfer
+    TransferIn {
+        counterparty: ClientId,
+    },
+}
+
+impl TxnType {
+    pub fn is_transfer(&self) -> bool {
+        matches!(
+            self,
+            TxnType::TransferOut { .. } | TxnType::TransferIn { .. }
+        )
+    }
+
+    pub fn counterparty(&self) -> Option<ClientId> {
+        match *self {
+            TxnType::TransferOut { counterparty } | TxnType::TransferIn { counterparty } => {
+                Some(counterparty)
+            }
+            TxnType::Deposit | TxnType::Withdrawal => None,
+        }
+    }
+}
+
+impl fmt::Display for TxnType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            TxnType::Deposit => "deposit",
+            TxnType::Withdrawal => "withdrawal",
+            TxnType::TransferOut { .. } => "transfer_out",
+            TxnType::TransferIn { .. } => "transfer_in",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Dispute lifecycle of a transaction.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TxnState {
+    Settled,
+    Disputed,
+    Resolved,
+    ChargedBack,
+    Reversed,
+}
+
+/// Actions on recorded transactions, driving the dispute lifecycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TxnAction {
+    Dispute,
+    Resolve,
+    Chargeback,
+    Refund,
+    Reverse,
+}
+
+impl TxnAction {
+    pub fn operation(&self) -> Operation {
+        match self {
+            TxnAction::Dispute => Operation::Dispute,
+            TxnAction::Resolve => Operation::Resolve,
+            TxnAction::Chargeback => Operation::Chargeback,
+            TxnAction::Refund => Operation::Refund,
+            TxnAction::Reverse => Operation::Reverse,
+        }
+    }
+}
+
+impl fmt::Display for TxnAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            TxnAction::Dispute => "dispute",
+            TxnAction::Resolve => "resolve",
+            TxnAction::Chargeback => "chargeback",
+            TxnAction::Refund => "refund",
+            TxnAction::Reverse => "reverse",
+        };
+        f.write_str(name)
+    }
+}
+
+impl TxnState {
+    /// Enforces legal transitions of the dispute lifecycle, charged back and reversed are terminal.
+    /// Disputed transactions can be disputed further, to extend partial disputes.
+    /// Refunds and reversals are only allowed outside of disputes.
+    pub fn next(self, action: TxnAction, allow_redispute: bool) -> Result<TxnState, EngineError> {
+        match (self, action) {
+            (TxnState::Settled, TxnAction::Dispute) => Ok(TxnState::Disputed),
+            (TxnState::Resolved, TxnAction::Dispute) if allow_redispute => Ok(TxnState::Disputed),
+            (TxnState::Resolved, TxnAction::Dispute) => Err(EngineError::RedisputeNotAllowed),
+            (TxnState::Disputed, TxnAction::Dispute) => Ok(TxnState::Disputed),
+            (TxnState::Disputed, TxnAction::Resolve) => Ok(TxnState::Resolved),
+            (TxnState::Disputed, TxnAction::Chargeback) => Ok(TxnState::ChargedBack),
+            (state @ (TxnState::Settled | TxnState::Resolved), TxnAction::Refund) => Ok(state),
+            (TxnState::Settled | TxnState::Resolved, TxnAction::Reverse) => Ok(TxnState::Reversed),
+            (state, action) => Err(EngineError::InvalidTransition { state, action }),
+        }
+    }
+}
+
+/// Type adjusted amounts moved by a transaction action.
+/// Available funds change by `-(held + charged_back + settled)`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Movement {
+    /// Change of held funds
+    pub held: Decimal,
+    /// Funds leaving the account via chargeback
+    pub charged_back: Decimal,
+    /// Funds leaving the account via settlement, -ve if entering
+    pub settled: Decimal,
+}
+
+impl Movement {
+    pub fn available(&self) -> Decimal {
+        // Note: subtracting, rather than negating, avoids -ve zero
+        Decimal::ZERO - self.held - self.charged_back - self.settled
+    }
 }
 
 /// Transaction maintained for disputes.
-#[derive(Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Txn {
     pub txn_type: TxnType,
     pub amount: Decimal,
+    pub state: TxnState,
+    /// Portion of the amount currently under dispute
+    pub disputed: Decimal,
+    /// Portion of the amount charged back
+    pub charged_back: Decimal,
+    /// Portion of the withdrawal amount refunded
+    pub refunded: Decimal,
+    /// Number of times the transaction has been disputed
+    pub dispute_count: u32,
+    /// Event time of the transaction, if known
+    pub timestamp: Option<Timestamp>,
+    /// Event time of the latest dispute, if known
+    pub disputed_at: Option<Timestamp>,
+    /// Events of the leg's client counted when the latest dispute was opened
+    pub disputed_seq: Option<u64>,
+    /// Fees charged on the transaction, non-refundable
+    pub fee: Decimal,
 }
 
 impl Txn {
-    pub fn type_adjusted_amount(&self) -> Decimal {
+    pub fn new(txn_type: TxnType, amount: Decimal, timestamp: Option<Timestamp>) -> Self {
+        Txn {
+            txn_type,
+            amount,
+            state: TxnState::Settled,
+            disputed: Decimal::ZERO,
+            charged_back: Decimal::ZERO,
+            refunded: Decimal::ZERO,
+            dispute_count: 0,
+            timestamp,
+            disputed_at: None,
+            disputed_seq: None,
+            fee: Decimal::ZERO,
+        }
+    }
+
+    /// Moves the transaction through the dispute lifecycle, subject to engine policies.
+    /// Disputes, chargebacks and refunds apply to the given portion, or all of the remaining amount if `None`.
+    /// An open dispute can be extended by further disputes, up to the full amount.
+    /// Dispute window is only enforced if both `now` and the transaction's timestamp are known.
+    /// `sequence` is the number of events of the client so far, recorded when a dispute opens.
+    pub fn transition(
+        &mut self,
+        action: TxnAction,
+        amount: Option<Decimal>,
+        config: &EngineConfig,
+        now: Option<Timestamp>,
+        sequence: u64,
+    ) -> Result<Movement, EngineError> {
+        let state = self.state.next(action, config.allow_redispute)?;
+        let movement = match action {
+            TxnAction::Dispute => {
+                let undisputed = self.amount - self.refunded - self.disputed;
+                if undisputed.is_zero() {
+                    return Err(EngineError::InvalidTransition {
+                        state: self.state,
+                        action,
+                    });
+                }
+                let portion = self.checked_portion(action, amount, undisputed)?;
+                if self.state != TxnState::Disputed {
+                    if let Some(limit) = config.max_disputes_per_txn {
+                        if self.dispute_count >= limit {
+                            return Err(EngineError::DisputeLimitExceeded(limit));
+                        }
+                    }
+                    if let (Some(window), Some(now), Some(timestamp)) =
+                        (config.dispute_window(), now, self.timestamp)
+                    {
+                        if now.saturating_sub(timestamp) > window {
+                            return Err(EngineError::DisputeWindowExpired(
+                                config.dispute_window_days.unwrap_or_default(),
+                            ));
+                        }
+                    }
+                    self.dispute_count += 1;
+                    self.disputed_at = now;
+                    self.disputed_seq = Some(sequence);
+                }
+                self.disputed += portion;
+                Movement {
+                    held: self.held_adjusted(portion),
+                    ..Default::default()
+                }
+            }
+            TxnAction::Resolve => {
+                let released = std::mem::take(&mut self.disputed);
+                Movement {
+                    held: self.held_adjusted(-released),
+                    ..Default::default()
+                }
+            }
+            TxnAction::Chargeback => {
+                let portion = self.checked_portion(action, amount, self.disputed)?;
+                let released = std::mem::take(&mut self.disputed);
+                self.charged_back += portion;
+                Movement {
+                    held: self.held_adjusted(-released),
+                    charged_back: self.type_adjusted(portion),
+                    ..Default::default()
+                }
+            }
+            TxnAction::Refund => {
+                if self.txn_type != TxnType::Withdrawal {
+                    return Err(EngineError::RefundNonWithdrawal);
+                }
+                let portion = self.checked_portion(action, amount, self.amount - self.refunded)?;
+                self.refunded += portion;
+                Movement {
+                    settled: -portion,
+                    ..Default::default()
+                }
+            }
+            TxnAction::Reverse => Movement {
+                settled: self.type_adjusted(self.amount - self.refunded),
+                ..Default::default()
+            },
+        };
+        self.state = state;
+        Ok(movement)
+    }
+
+    /// Requested portion, not exceeding the limit, defaulting to the limit.
+    fn checked_portion(
+        &self,
+        action: TxnAction,
+        requested: Option<Decimal>,
+        limit: Decimal,
+    ) -> Result<Decimal, EngineError> {
+        match requested {
+            Some(requested) if requested > limit => Err(EngineError::AmountExceeded {
+                action,
+                requested,
+                limit,
+            }),
+            Some(requested) => Ok(requested),
+            None => Ok(limit),
+        }
+    }
+
+    fn type_adjusted(&self, amount: Decimal) -> Decimal {
+        match self.txn_type {
+            TxnType::Deposit | TxnType::TransferIn { .. } => amount,
+            TxnType::Withdrawal | TxnType::TransferOut { .. } => -amount,
+        }
+    }
+
+    /// Disputed transfers hold funds at the destination only.
+    fn held_adjusted(&self, amount: Decimal) -> Decimal {
         match self.txn_type {
-            TxnType::Deposit => self.amount,
-            TxnType::Withdrawal => -self.amount,
+            TxnType::TransferOut { .. } => Decimal::ZERO,
+            _ => self.type_adjusted(amount),
+        }
+    }
+
+    pub fn type_adjusted_amount(&self) -> Decimal {
+        self.type_adjusted(self.amount)
+    }
+
+    /// Amount currently held due to dispute.
+    pub fn held_amount(&self) -> Decimal {
+        self.held_adjusted(self.disputed)
+    }
+
+    /// Amount contributing to the account total, charged back and refunded portions no longer do, fees always do.
+    pub fn net_amount(&self) -> Decimal {
+        let amount = match self.state {
+            TxnState::Reversed => Decimal::ZERO,
+            _ => self.type_adjusted(self.amount - self.charged_back - self.refunded),
+        };
+        amount - self.fee
+    }
+}
+
+/// Read-only view of a recorded transaction.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct TxnView {
+    #[serde(rename = "client")]
+    pub client_id: ClientId,
+    #[serde(rename = "tx")]
+    pub txn_id: TxnId,
+    #[serde(rename = "type", serialize_with = "serialize_display")]
+    pub txn_type: TxnType,
+    /// Other side of a transfer
+    pub counterparty: Option<ClientId>,
+    #[serde(serialize_with = "serialize_decimal_4_places")]
+    pub amount: Decimal,
+    pub state: TxnState,
+    #[serde(serialize_with = "serialize_decimal_4_places")]
+    pub disputed: Decimal,
+    #[serde(serialize_with = "serialize_decimal_4_places")]
+    pub charged_back: Decimal,
+    #[serde(serialize_with = "serialize_decimal_4_places")]
+    pub refunded: Decimal,
+    #[serde(serialize_with = "serialize_decimal_4_places")]
+    pub fee: Decimal,
+    pub timestamp: Option<Timestamp>,
+    pub disputed_at: Option<Timestamp>,
+    /// Number of times the transaction has been disputed
+    pub dispute_count: u32,
+    /// Events of the client since the open dispute was opened
+    pub dispute_age_events: Option<u64>,
+}
+
+impl TxnView {
+    /// Views the transaction, aging any open dispute against the client's `events` so far.
+    pub fn new(client_id: ClientId, txn_id: TxnId, txn: &Txn, events: u64) -> Self {
+        TxnView {
+            client_id,
+            txn_id,
+            txn_type: txn.txn_type,
+            counterparty: txn.txn_type.counterparty(),
+            amount: txn.amount,
+            state: txn.state,
+            disputed: txn.disputed,
+            charged_back: txn.charged_back,
+            refunded: txn.refunded,
+            fee: txn.fee,
+            timestamp: txn.timestamp,
+            disputed_at: txn.disputed_at,
+            dispute_count: txn.dispute_count,
+            dispute_age_events: txn
+                .disputed_seq
+                .filter(|_| txn.state == TxnState::Disputed)
+                .map(|seq| events.saturating_sub(seq)),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SECONDS_PER_DAY;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_state_transitions() {
+        use TxnAction::*;
+        use TxnState::*;
+
+        assert_eq!(Disputed, Settled.next(Dispute, true).unwrap());
+        assert_eq!(Resolved, Disputed.next(Resolve, true).unwrap());
+        assert_eq!(ChargedBack, Disputed.next(Chargeback, true).unwrap());
+        assert_eq!(Disputed, Resolved.next(Dispute, true).unwrap());
+        assert_eq!(
+            Err(EngineError::RedisputeNotAllowed),
+            Resolved.next(Dispute, false)
+        );
+        assert_eq!(Disputed, Disputed.next(Dispute, true).unwrap());
+        assert_eq!(
+            Err(EngineError::InvalidTransition {
+                state: Settled,
+                action: Resolve
+            }),
+            Settled.next(Resolve, true)
+        );
+        assert_eq!(Settled, Settled.next(Refund, true).unwrap());
+        assert_eq!(Resolved, Resolved.next(Refund, true).unwrap());
+        assert_eq!(Reversed, Settled.next(Reverse, true).unwrap());
+        assert_eq!(Reversed, Resolved.next(Reverse, true).unwrap());
+        for action in [Dispute, Resolve, Chargeback, Refund, Reverse] {
+            assert!(ChargedBack.next(action, true).is_err());
+            assert!(Reversed.next(action, true).is_err());
+        }
+        assert!(Disputed.next(Refund, true).is_err());
+        assert!(Disputed.next(Reverse, true).is_err());
+        for state in [Settled, Resolved] {
+            assert!(state.next(Resolve, true).is_err());
+            assert!(state.next(Chargeback, true).is_err());
+        }
+    }
+
+    #[test]
+    fn test_dispute_limit() {
+        let config = EngineConfig {
+            max_disputes_per_txn: Some(2),
+            ..Default::default()
+        };
+        let mut txn = Txn::new(TxnType::Deposit, Decimal::ONE, None);
+        for _ in 0..2 {
+            txn.transition(TxnAction::Dispute, None, &config, None, 0)
+                .unwrap();
+            txn.transition(TxnAction::Resolve, None, &config, None, 0)
+                .unwrap();
+        }
+        assert_eq!(
+            Err(EngineError::DisputeLimitExceeded(2)),
+            txn.transition(TxnAction::Dispute, None, &config, None, 0)
+        );
+        assert_eq!(TxnState::Resolved, txn.state);
+        assert_eq!(2, txn.dispute_count);
+    }
+
+    #[test]
+    fn test_dispute_window() {
+        let config = EngineConfig {
+            dispute_window_days: Some(1),
+            ..Default::default()
+        };
+        let mut txn = Txn::new(TxnType::Deposit, Decimal::ONE, Some(1000));
+        assert_eq!(
+            Err(EngineError::DisputeWindowExpired(1)),
+            txn.transition(
+                TxnAction::Dispute,
+                None,
+                &config,
+                Some(1000 + SECONDS_PER_DAY + 1),
+                0
+            )
+        );
+        assert_eq!(TxnState::Settled, txn.state);
+
+        txn.transition(
+            TxnAction::Dispute,
+            None,
+            &config,
+            Some(1000 + SECONDS_PER_DAY),
+            0,
+        )
+        .unwrap();
+        assert_eq!(Some(1000 + SECONDS_PER_DAY), txn.disputed_at);
+
+        // no timestamp, no window
+        let mut txn = Txn::new(TxnType::Deposit, Decimal::ONE, None);
+        txn.transition(TxnAction::Dispute, None, &config, Some(u64::MAX), 0)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_partial_dispute_chargeback() {
+        let config = EngineConfig::default();
+        let mut txn = Txn::new(TxnType::Withdrawal, dec!(100), None);
+        assert_eq!(
+            Movement {
+                held: dec!(-30),
+                ..Default::default()
+            },
+            txn.transition(TxnAction::Dispute, Some(dec!(30)), &config, None, 0)
+                .unwrap()
+        );
+        assert_eq!(
+            Err(EngineError::AmountExceeded {
+                action: TxnAction::Dispute,
+                requested: dec!(71),
+                limit: dec!(70)
+            }),
+            txn.transition(TxnAction::Dispute, Some(dec!(71)), &config, None, 0)
+        );
+        txn.transition(TxnAction::Dispute, Some(dec!(20)), &config, None, 0)
+            .unwrap();
+        assert_eq!((dec!(50), 1), (txn.disputed, txn.dispute_count));
+        assert_eq!(
+            Err(EngineError::AmountExceeded {
+                action: TxnAction::Chargeback,
+                requested: dec!(51),
+                limit: dec!(50)
+            }),
+            txn.transition(TxnAction::Chargeback, Some(dec!(51)), &config, None, 0)
+        );
+        assert_eq!(
+            Movement {
+                held: dec!(50),
+                charged_back: dec!(-40),
+                ..Default::default()
+            },
+            txn.transition(TxnAction::Chargeback, Some(dec!(40)), &config, None, 0)
+                .unwrap()
+        );
+        assert_eq!(TxnState::ChargedBack, txn.state);
+        assert_eq!(Decimal::ZERO, txn.held_amount());
+        assert_eq!(dec!(-60), txn.net_amount());
+    }
+
+    #[test]
+    fn test_fully_disputed() {
+        let config = EngineConfig::default();
+        let mut txn = Txn::new(TxnType::Deposit, dec!(100), None);
+        txn.transition(TxnAction::Dispute, None, &config, None, 0)
+            .unwrap();
+        assert_eq!(
+            Err(EngineError::InvalidTransition {
+                state: TxnState::Disputed,
+                action: TxnAction::Dispute
+            }),
+            txn.transition(TxnAction::Dispute, None, &config, None, 0)
+        );
+    }
+
+    #[test]
+    fn test_refund_reverse() {
+        let config = EngineConfig::default();
+        let mut txn = Txn::new(TxnType::Withdrawal, dec!(100), None);
+        assert_eq!(
+            dec!(30),
+            txn.transition(TxnAction::Refund, Some(dec!(30)), &config, None, 0)
+                .unwrap()
+                .available()
+        );
+        assert_eq!(
+            Err(EngineError::AmountExceeded {
+                action: TxnAction::Dispute,
+                requested: dec!(71),
+                limit: dec!(70)
+            }),
+            txn.transition(TxnAction::Dispute, Some(dec!(71)), &config, None, 0)
+        );
+        assert_eq!(dec!(-70), txn.net_amount());
+        assert_eq!(
+            dec!(70),
+            txn.transition(TxnAction::Reverse, None, &config, None, 0)
+                .unwrap()
+                .available()
+        );
+        assert_eq!(Decimal::ZERO, txn.net_amount());
+
+        let mut txn = Txn::new(TxnType::Deposit, dec!(100), None);
+        assert_eq!(
+            Err(EngineError::RefundNonWithdrawal),
+            txn.transition(TxnAction::Refund, None, &config, None, 0)
+        );
+    }
+}
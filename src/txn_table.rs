@@ -0,0 +1,382 @@
+use crate::{
+    txn::{Txn, TxnState, TxnType},
+    types::{ClientId, Timestamp, TxnId},
+};
+use rust_decimal::Decimal;
+use std::{collections::HashMap, mem::size_of};
+
+/// Decimal places of fixed-point amounts.
+const SCALE: u32 = 4;
+/// Marks `None` timestamps.
+const NO_TIMESTAMP: Timestamp = Timestamp::MAX;
+
+// Slot tag bits: slot status, transaction type and transaction state.
+const STATUS_MASK: u8 = 0b11;
+const NO_SEQUENCE: u32 = u32::MAX;
+const EMPTY: u8 = 0;
+const OCCUPIED: u8 = 1;
+const TOMBSTONE: u8 = 2;
+const TYPE_SHIFT: u8 = 2;
+const STATE_SHIFT: u8 = 4;
+
+/// Transaction packed into fixed-point amounts and tag bits, 72 bytes rather than ~130 of `(TxnId, Txn)`.
+#[derive(Debug, Default, Clone, Copy)]
+struct Slot {
+    timestamp: Timestamp,
+    disputed_at: Timestamp,
+    /// amount, disputed, charged back, refunded and fee, scaled to `SCALE` decimal places
+    amounts: [i64; 5],
+    txn_id: TxnId,
+    /// `NO_SEQUENCE` if unknown
+    disputed_seq: u32,
+    dispute_count: u16,
+    counterparty: ClientId,
+    /// Original scale of every amount, 3 bits each, preserving their representation
+    scales: u16,
+    tag: u8,
+}
+
+impl Slot {
+    fn status(&self) -> u8 {
+        self.tag & STATUS_MASK
+    }
+
+    /// Packs the transaction, if all of its amounts fit the fixed-point representation.
+    fn pack(txn_id: TxnId, txn: &Txn) -> Option<Self> {
+        let mut slot = Slot {
+            timestamp: pack_timestamp(txn.timestamp)?,
+            disputed_at: pack_timestamp(txn.disputed_at)?,
+            txn_id,
+            disputed_seq: pack_sequence(txn.disputed_seq)?,
+            dispute_count: u16::try_from(txn.dispute_count).ok()?,
+            ..Default::default()
+        };
+        let amounts = [
+            txn.amount,
+            txn.disputed,
+            txn.charged_back,
+            txn.refunded,
+            txn.fee,
+        ];
+        for (i, amount) in amounts.into_iter().enumerate() {
+            let (value, scale) = pack_decimal(amount)?;
+            slot.amounts[i] = value;
+            slot.scales |= (scale as u16) << (i * 3);
+        }
+        let txn_type = match txn.txn_type {
+            TxnType::Deposit => 0,
+            TxnType::Withdrawal => 1,
+            TxnType::TransferOut { counterparty } => {
+                slot.counterparty = counterparty;
+                2
+            }
+            TxnType::TransferIn { counterparty } => {
+                slot.counterparty = counterparty;
+                3
+            }
+        };
+        let state = match txn.state {
+            TxnState::Settled => 0,
+            TxnState::Disputed => 1,
+            TxnState::Resolved => 2,
+            TxnState::ChargedBack => 3,
+            TxnState::Reversed => 4,
+        };
+        slot.tag = OCCUPIED | txn_type << TYPE_SHIFT | state << STATE_SHIFT;
+        Some(slot)
+    }
+
+    fn unpack(&self) -> Txn {
+        let counterparty = self.counterparty;
+        let txn_type = match (self.tag >> TYPE_SHIFT) & 0b11 {
+            0 => TxnType::Deposit,
+            1 => TxnType::Withdrawal,
+            2 => TxnType::TransferOut { counterparty },
+            _ => TxnType::TransferIn { counterparty },
+        };
+        let state = match self.tag >> STATE_SHIFT {
+            0 => TxnState::Settled,
+            1 => TxnState::Disputed,
+            2 => TxnState::Resolved,
+            3 => TxnState::ChargedBack,
+            _ => TxnState::Reversed,
+        };
+        let amount = |i: usize| {
+            let scale = u32::from(self.scales >> (i * 3) & 0b111);
+            Decimal::new(self.amounts[i] / 10_i64.pow(SCALE - scale), scale)
+        };
+        Txn {
+            txn_type,
+            amount: amount(0),
+            state,
+            disputed: amount(1),
+            charged_back: amount(2),
+            refunded: amount(3),
+            dispute_count: u32::from(self.dispute_count),
+            timestamp: unpack_timestamp(self.timestamp),
+            disputed_at: unpack_timestamp(self.disputed_at),
+            disputed_seq: (self.disputed_seq != NO_SEQUENCE)
+                .then_some(u64::from(self.disputed_seq)),
+            fee: amount(4),
+        }
+    }
+}
+
+fn pack_decimal(value: Decimal) -> Option<(i64, u32)> {
+    let scale = value.scale();
+    // Note: -ve zero has no fixed-point representation
+    if scale > SCALE || (value.is_zero() && value.is_sign_negative()) {
+        return None;
+    }
+    let value = i64::try_from(value.mantissa()).ok()?;
+    Some((value.checked_mul(10_i64.pow(SCALE - scale))?, scale))
+}
+
+fn pack_timestamp(timestamp: Option<Timestamp>) -> Option<Timestamp> {
+    match timestamp {
+        None => Some(NO_TIMESTAMP),
+        Some(NO_TIMESTAMP) => None,
+        Some(timestamp) => Some(timestamp),
+    }
+}
+
+fn pack_sequence(sequence: Option<u64>) -> Option<u32> {
+    match sequence {
+        None => Some(NO_SEQUENCE),
+        Some(sequence) => u32::try_from(sequence).ok().filter(|s| *s != NO_SEQUENCE),
+    }
+}
+
+fn unpack_timestamp(timestamp: Timestamp) -> Option<Timestamp> {
+    (timestamp != NO_TIMESTAMP).then_some(timestamp)
+}
+
+/// Transactions of an account, keyed by `TxnId`, in an open-addressing table of packed slots with linear probing.
+/// Transactions with amounts beyond 4 decimal places, or beyond `i64` once scaled, overflow into a `HashMap`.
+/// Lookups return copies, updates go through `insert()` or `update()`.
+#[derive(Debug, Default)]
+pub struct TxnTable {
+    slots: Vec<Slot>,
+    /// Occupied slots
+    len: usize,
+    /// Slots of removed transactions, kept for probing until the next resize
+    tombstones: usize,
+    overflow: HashMap<TxnId, Txn>,
+}
+
+impl TxnTable {
+    pub fn len(&self) -> usize {
+        self.len + self.overflow.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Approximate bytes allocated.
+    pub fn memory_bytes(&self) -> usize {
+        // Note: hash map buckets carry a control byte each
+        self.slots.capacity() * size_of::<Slot>()
+            + self.overflow.capacity() * (size_of::<(TxnId, Txn)>() + 1)
+    }
+
+    fn home(&self, txn_id: TxnId) -> usize {
+        // Fibonacci hashing spreads sequential ids, the top bits index the power of 2 table
+        let hash = u64::from(txn_id).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+        (hash >> (64 - self.slots.len().trailing_zeros())) as usize
+    }
+
+    fn find(&self, txn_id: TxnId) -> Option<usize> {
+        if self.len == 0 {
+            return None;
+        }
+        let mask = self.slots.len() - 1;
+        let mut i = self.home(txn_id);
+        loop {
+            let slot = &self.slots[i];
+            match slot.status() {
+                EMPTY => return None,
+                OCCUPIED if slot.txn_id == txn_id => return Some(i),
+                _ => i = (i + 1) & mask,
+            }
+        }
+    }
+
+    pub fn contains_key(&self, txn_id: &TxnId) -> bool {
+        self.find(*txn_id).is_some() || self.overflow.contains_key(txn_id)
+    }
+
+    pub fn get(&self, txn_id: &TxnId) -> Option<Txn> {
+        match self.find(*txn_id) {
+            Some(i) => Some(self.slots[i].unpack()),
+            None => self.overflow.get(txn_id).cloned(),
+        }
+    }
+
+    /// Records the transaction, replacing any with the same id.
+    pub fn insert(&mut self, txn_id: TxnId, txn: Txn) {
+        let Some(slot) = Slot::pack(txn_id, &txn) else {
+            self.remove_slot(txn_id);
+            self.overflow.insert(txn_id, txn);
+            return;
+        };
+        self.overflow.remove(&txn_id);
+        if let Some(i) = self.find(txn_id) {
+            self.slots[i] = slot;
+            return;
+        }
+        // Keeps load, including tombstones, at most 7/8, guaranteeing empty slots end every probe
+        if (self.len + self.tombstones + 1) * 8 > self.slots.len() * 7 {
+            self.resize();
+        }
+        let mask = self.slots.len() - 1;
+        let mut i = self.home(txn_id);
+        while self.slots[i].status() == OCCUPIED {
+            i = (i + 1) & mask;
+        }
+        if self.slots[i].status() == TOMBSTONE {
+            self.tombstones -= 1;
+        }
+        self.slots[i] = slot;
+        self.len += 1;
+    }
+
+    /// Applies the change to the transaction, if it exists.
+    pub fn update(&mut self, txn_id: &TxnId, change: impl FnOnce(&mut Txn)) {
+        if let Some(mut txn) = self.get(txn_id) {
+            change(&mut txn);
+            self.insert(*txn_id, txn);
+        }
+    }
+
+    pub fn remove(&mut self, txn_id: &TxnId) -> Option<Txn> {
+        self.remove_slot(*txn_id)
+            .or_else(|| self.overflow.remove(txn_id))
+    }
+
+    fn remove_slot(&mut self, txn_id: TxnId) -> Option<Txn> {
+        let i = self.find(txn_id)?;
+        let txn = self.slots[i].unpack();
+        self.slots[i].tag = TOMBSTONE;
+        self.len -= 1;
+        self.tombstones += 1;
+        Some(txn)
+    }
+
+    /// Rehashes into a table at most half full, dropping tombstones.
+    fn resize(&mut self) {
+        let capacity = ((self.len + 1) * 2).next_power_of_two().max(8);
+        let slots = std::mem::replace(&mut self.slots, vec![Slot::default(); capacity]);
+        self.tombstones = 0;
+        let mask = capacity - 1;
+        for slot in slots.into_iter().filter(|slot| slot.status() == OCCUPIED) {
+            let mut i = self.home(slot.txn_id);
+            while self.slots[i].status() != EMPTY {
+                i = (i + 1) & mask;
+            }
+            self.slots[i] = slot;
+        }
+    }
+
+    /// All transactions, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (TxnId, Txn)> + '_ {
+        self.slots
+            .iter()
+            .filter(|slot| slot.status() == OCCUPIED)
+            .map(|slot| (slot.txn_id, slot.unpack()))
+            .chain(
+                self.overflow
+                    .iter()
+                    .map(|(&txn_id, txn)| (txn_id, txn.clone())),
+            )
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = Txn> + '_ {
+        self.iter().map(|(_, txn)| txn)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn assert_same(expected: &Txn, actual: &Txn) {
+        // Note: Debug output differs for equal decimals of different scales
+        assert_eq!(format!("{expected:?}"), format!("{actual:?}"));
+    }
+
+    #[test]
+    fn test_pack_round_trip() {
+        let mut txn = Txn::new(
+            TxnType::TransferOut { counterparty: 7 },
+            dec!(1.50),
+            Some(9),
+        );
+        txn.state = TxnState::ChargedBack;
+        txn.disputed = dec!(0.0001);
+        txn.charged_back = dec!(-922337203685477.5807);
+        txn.fee = dec!(15);
+        txn.dispute_count = 3;
+        txn.disputed_at = Some(0);
+        txn.disputed_seq = Some(12);
+        assert_same(&txn, &Slot::pack(1, &txn).unwrap().unpack());
+        assert_eq!(72, size_of::<Slot>());
+
+        for amount in [dec!(0.00001), dec!(922337203685477.5808), -Decimal::ZERO] {
+            let txn = Txn::new(TxnType::Deposit, amount, None);
+            assert!(Slot::pack(1, &txn).is_none());
+        }
+        let txn = Txn::new(TxnType::Deposit, dec!(1), Some(NO_TIMESTAMP));
+        assert!(Slot::pack(1, &txn).is_none());
+        let mut txn = Txn::new(TxnType::Deposit, dec!(1), None);
+        txn.disputed_seq = Some(u64::from(NO_SEQUENCE));
+        assert!(Slot::pack(1, &txn).is_none());
+        txn.disputed_seq = None;
+        txn.dispute_count = u32::from(u16::MAX) + 1;
+        assert!(Slot::pack(1, &txn).is_none());
+    }
+
+    #[test]
+    fn test_matches_hash_map() {
+        let mut table = TxnTable::default();
+        let mut map = HashMap::new();
+        for i in 0..5000_u32 {
+            let txn_id = i.wrapping_mul(2_654_435_761) % 3000;
+            let amount = match i % 5 {
+                0 => dec!(0.123456), // overflows
+                _ => Decimal::new(i64::from(i), i % 5),
+            };
+            match i % 4 {
+                3 => assert_eq!(
+                    map.remove(&txn_id).map(|txn: Txn| format!("{txn:?}")),
+                    table.remove(&txn_id).map(|txn| format!("{txn:?}"))
+                ),
+                _ => {
+                    let txn = Txn::new(TxnType::Withdrawal, amount, Some(u64::from(i)));
+                    map.insert(txn_id, txn.clone());
+                    table.insert(txn_id, txn);
+                }
+            }
+            assert_eq!(map.len(), table.len());
+        }
+        for txn_id in 0..3000 {
+            assert_eq!(map.contains_key(&txn_id), table.contains_key(&txn_id));
+            if let Some(expected) = map.get(&txn_id) {
+                assert_same(expected, &table.get(&txn_id).unwrap());
+            }
+        }
+        assert_eq!(map.len(), table.iter().count());
+
+        table.update(&map.keys().next().copied().unwrap(), |txn| {
+            txn.state = TxnState::Disputed
+        });
+        assert_eq!(
+            1,
+            table
+                .values()
+                .filter(|txn| txn.state == TxnState::Disputed)
+                .count()
+        );
+    }
+}
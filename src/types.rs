@@ -4,21 +4,59 @@ use serde::{Deserialize, Deserializer};
 /// User friendly type aliases
 pub type ClientId = u16;
 pub type TxnId = u32;
+/// Event time, in seconds since the unix epoch
+pub type Timestamp = u64;
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq)]
 pub struct TxnEvent {
     pub client_id: ClientId,
     pub txn_id: TxnId,
+    pub timestamp: Option<Timestamp>,
     pub detail: TxnEventDetail,
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq)]
 pub enum TxnEventDetail {
-    Deposit { amount: PositiveDecimal },
-    Withdrawal { amount: PositiveDecimal },
-    Dispute,
+    Deposit {
+        amount: PositiveDecimal,
+    },
+    Withdrawal {
+        amount: PositiveDecimal,
+    },
+    Dispute {
+        amount: Option<PositiveDecimal>,
+    },
     Resolve,
-    Chargeback,
+    Chargeback {
+        amount: Option<PositiveDecimal>,
+    },
+    /// Atomic move of funds from the client to the destination client
+    Transfer {
+        destination_client_id: ClientId,
+        amount: PositiveDecimal,
+    },
+    /// Credit referencing an earlier withdrawal, refunding all of it if `amount` is missing
+    Refund {
+        amount: Option<PositiveDecimal>,
+    },
+    /// Voids an earlier deposit/withdrawal
+    Reversal,
+}
+
+impl TxnEventDetail {
+    /// Event type, as named in the input feed.
+    pub fn name(&self) -> &'static str {
+        match self {
+            TxnEventDetail::Deposit { .. } => "deposit",
+            TxnEventDetail::Withdrawal { .. } => "withdrawal",
+            TxnEventDetail::Dispute { .. } => "dispute",
+            TxnEventDetail::Resolve => "resolve",
+            TxnEventDetail::Chargeback { .. } => "chargeback",
+            TxnEventDetail::Transfer { .. } => "transfer",
+            TxnEventDetail::Refund { .. } => "refund",
+            TxnEventDetail::Reversal => "reversal",
+        }
+    }
 }
 
 /// Deserialize for TxnEvent, enforcing semantics for every transaction
@@ -35,6 +73,9 @@ impl<'de> Deserialize<'de> for TxnEvent {
             Dispute,
             Resolve,
             Chargeback,
+            Transfer,
+            Refund,
+            Reversal,
         }
 
         #[derive(Deserialize, Debug)]
@@ -46,6 +87,9 @@ impl<'de> Deserialize<'de> for TxnEvent {
             #[serde(rename = "tx")]
             txn_id: TxnId,
             amount: Option<PositiveDecimal>,
+            timestamp: Option<Timestamp>,
+            #[serde(rename = "destination")]
+            destination_client_id: Option<ClientId>,
         }
 
         let event = TxnEventPrivate::deserialize(deserializer)?;
@@ -63,13 +107,34 @@ impl<'de> Deserialize<'de> for TxnEvent {
                     .ok_or(serde::de::Error::missing_field("amount"))?;
                 Ok(TxnEventDetail::Withdrawal { amount })
             }
-            TxnEventType::Dispute => Ok(TxnEventDetail::Dispute),
+            TxnEventType::Dispute => Ok(TxnEventDetail::Dispute {
+                amount: event.amount,
+            }),
             TxnEventType::Resolve => Ok(TxnEventDetail::Resolve),
-            TxnEventType::Chargeback => Ok(TxnEventDetail::Chargeback),
+            TxnEventType::Chargeback => Ok(TxnEventDetail::Chargeback {
+                amount: event.amount,
+            }),
+            TxnEventType::Transfer => {
+                let destination_client_id = event
+                    .destination_client_id
+                    .ok_or(serde::de::Error::missing_field("destination"))?;
+                let amount = event
+                    .amount
+                    .ok_or(serde::de::Error::missing_field("amount"))?;
+                Ok(TxnEventDetail::Transfer {
+                    destination_client_id,
+                    amount,
+                })
+            }
+            TxnEventType::Refund => Ok(TxnEventDetail::Refund {
+                amount: event.amount,
+            }),
+            TxnEventType::Reversal => Ok(TxnEventDetail::Reversal),
         }?;
         Ok(TxnEvent {
             client_id: event.client_id,
             txn_id: event.txn_id,
+            timestamp: event.timestamp,
             detail,
         })
     }
@@ -99,6 +164,7 @@ chargeback,2,102,",
                 TxnEvent {
                     client_id: 1,
                     txn_id: 101,
+                    timestamp: None,
                     detail: TxnEventDetail::Deposit {
                         amount: dec!(123.45).try_into()?,
                     },
@@ -106,6 +172,7 @@ chargeback,2,102,",
                 TxnEvent {
                     client_id: 2,
                     txn_id: 102,
+                    timestamp: None,
                     detail: TxnEventDetail::Withdrawal {
                         amount: dec!(67.89).try_into()?,
                     }
@@ -113,22 +180,26 @@ chargeback,2,102,",
                 TxnEvent {
                     client_id: 1,
                     txn_id: 101,
-                    detail: TxnEventDetail::Dispute
+                    timestamp: None,
+                    detail: TxnEventDetail::Dispute { amount: None }
                 },
                 TxnEvent {
                     client_id: 2,
                     txn_id: 102,
-                    detail: TxnEventDetail::Dispute
+                    timestamp: None,
+                    detail: TxnEventDetail::Dispute { amount: None }
                 },
                 TxnEvent {
                     client_id: 1,
                     txn_id: 101,
+                    timestamp: None,
                     detail: TxnEventDetail::Resolve
                 },
                 TxnEvent {
                     client_id: 2,
                     txn_id: 102,
-                    detail: TxnEventDetail::Chargeback
+                    timestamp: None,
+                    detail: TxnEventDetail::Chargeback { amount: None }
                 },
             ],
             events
@@ -136,6 +207,61 @@ chargeback,2,102,",
         Ok(())
     }
 
+    #[test]
+    fn test_deserialize_refund_reversal() -> anyhow::Result<()> {
+        let events = read_csv_contents(
+            "type,client,tx,amount
+refund,1,101,
+refund,1,102,2.5
+reversal,1,103,",
+        )
+        .collect::<Result<Vec<TxnEvent>, _>>()?;
+
+        assert_eq!(
+            vec![
+                TxnEventDetail::Refund { amount: None },
+                TxnEventDetail::Refund {
+                    amount: Some(dec!(2.5).try_into()?)
+                },
+                TxnEventDetail::Reversal,
+            ],
+            events.into_iter().map(|e| e.detail).collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_deserialize_transfer() -> anyhow::Result<()> {
+        let events = read_csv_contents(
+            "type,client,tx,amount,destination
+transfer,1,101,12.5,2",
+        )
+        .collect::<Result<Vec<TxnEvent>, _>>()?;
+        assert_eq!(
+            vec![TxnEvent {
+                client_id: 1,
+                txn_id: 101,
+                timestamp: None,
+                detail: TxnEventDetail::Transfer {
+                    destination_client_id: 2,
+                    amount: dec!(12.5).try_into()?
+                }
+            }],
+            events
+        );
+
+        let res = read_csv_contents(
+            "type,client,tx,amount
+transfer,1,101,12.5",
+        )
+        .collect::<Result<Vec<TxnEvent>, _>>();
+        assert!(res
+            .unwrap_err()
+            .to_string()
+            .contains("missing field `destination`"));
+        Ok(())
+    }
+
     #[test]
     fn test_deserialize_err_no_headers() {
         let res = read_csv_contents(
@@ -171,4 +297,78 @@ BOGUS_TYPE,1,101,123.45",
         .collect::<Result<Vec<TxnEvent>, _>>();
         assert!(res.unwrap_err().to_string().contains("BOGUS_TYPE"));
     }
+
+    #[test]
+    fn test_deserialize_timestamp() -> anyhow::Result<()> {
+        let events = read_csv_contents(
+            "type,client,tx,amount,timestamp
+deposit,1,101,123.45,1700000000
+dispute,1,101,,",
+        )
+        .collect::<Result<Vec<TxnEvent>, _>>()?;
+
+        assert_eq!(
+            vec![
+                TxnEvent {
+                    client_id: 1,
+                    txn_id: 101,
+                    timestamp: Some(1700000000),
+                    detail: TxnEventDetail::Deposit {
+                        amount: dec!(123.45).try_into()?,
+                    },
+                },
+                TxnEvent {
+                    client_id: 1,
+                    txn_id: 101,
+                    timestamp: None,
+                    detail: TxnEventDetail::Dispute { amount: None }
+                },
+            ],
+            events
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_deserialize_partial_dispute() -> anyhow::Result<()> {
+        let events = read_csv_contents(
+            "type,client,tx,amount
+dispute,1,101,12.5
+chargeback,1,101,2.5",
+        )
+        .collect::<Result<Vec<TxnEvent>, _>>()?;
+
+        assert_eq!(
+            vec![
+                TxnEvent {
+                    client_id: 1,
+                    txn_id: 101,
+                    timestamp: None,
+                    detail: TxnEventDetail::Dispute {
+                        amount: Some(dec!(12.5).try_into()?)
+                    }
+                },
+                TxnEvent {
+                    client_id: 1,
+                    txn_id: 101,
+                    timestamp: None,
+                    detail: TxnEventDetail::Chargeback {
+                        amount: Some(dec!(2.5).try_into()?)
+                    }
+                },
+            ],
+            events
+        );
+
+        let res = read_csv_contents(
+            "type,client,tx,amount
+dispute,1,101,-1",
+        )
+        .collect::<Result<Vec<TxnEvent>, _>>();
+        assert!(res
+            .unwrap_err()
+            .to_string()
+            .contains("value must be positive and non-zero"));
+        Ok(())
+    }
 }
@@ -1,10 +1,10 @@
 use crate::types::TxnEvent;
 use csv::{ReaderBuilder, Trim, WriterBuilder};
 use serde::Serialize;
-use std::fs::File;
+use std::io::Read;
 
-// Read in CSV file, return an Iterator<Item=Result<TxnEvent>>
-pub fn read_csv_file(file: File) -> impl Iterator<Item = csv::Result<TxnEvent>> {
+// Read in CSV file, or any other reader, return an Iterator<Item=Result<TxnEvent>>
+pub fn read_csv_file<R: Read>(file: R) -> impl Iterator<Item = csv::Result<TxnEvent>> {
     let reader = ReaderBuilder::new()
         .has_headers(true)
         .trim(Trim::All)
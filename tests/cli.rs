@@ -0,0 +1,187 @@
+//! Integration tests of the binary: argument handling, snapshots on stdout, logs and reports on stderr, exit codes.
+use assert_cmd::Command;
+use predicates::prelude::*;
+use std::path::PathBuf;
+
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name)
+}
+
+fn cmd() -> Command {
+    let mut cmd = Command::cargo_bin("payments-engine").unwrap();
+    // Note: default log level, regardless of the environment running the tests
+    cmd.env_remove("RUST_LOG");
+    cmd
+}
+
+const SNAPSHOTS: &str = "client,available,held,total,locked
+1,123.45,0,123.45,false
+2,77.89,0,77.89,true
+3,0,10,10,false
+";
+
+#[test]
+fn test_snapshots_on_stdout() {
+    cmd()
+        .arg(fixture("transactions.csv"))
+        .assert()
+        .success()
+        .stdout(SNAPSHOTS)
+        .stderr(predicate::str::contains("Batch processed").not())
+        .stderr(predicate::str::contains("client,available").not());
+}
+
+#[test]
+fn test_sharded_and_parallel_parsing_match() {
+    cmd()
+        .arg(fixture("transactions.csv"))
+        .args(["--shards", "4"])
+        .assert()
+        .success()
+        .stdout(SNAPSHOTS);
+    cmd()
+        .arg(fixture("transactions.csv"))
+        .args(["--parse-threads", "2", "--chunk-size", "2"])
+        .assert()
+        .success()
+        .stdout(SNAPSHOTS);
+}
+
+#[test]
+fn test_sharded_cross_shard_transfers_match() {
+    const TRANSFER_SNAPSHOTS: &str = "client,available,held,total,locked
+1,70,0,70,false
+2,15,30,45,false
+3,5,0,5,false
+";
+    cmd()
+        .arg(fixture("transfers.csv"))
+        .assert()
+        .success()
+        .stdout(TRANSFER_SNAPSHOTS);
+    cmd()
+        .arg(fixture("transfers.csv"))
+        .args(["--shards", "2", "--verify"])
+        .assert()
+        .success()
+        .stdout(TRANSFER_SNAPSHOTS);
+}
+
+#[test]
+fn test_invalid_records_warn() {
+    cmd()
+        .arg(fixture("invalid.csv"))
+        .assert()
+        .success()
+        .stdout(
+            "client,available,held,total,locked
+1,60,0,60,false
+",
+        )
+        .stderr(predicate::str::contains("WARN"))
+        .stderr(predicate::str::contains("Error reading event").count(2))
+        .stderr(predicate::str::contains("Error processing event").count(1));
+}
+
+#[test]
+fn test_huge_amounts_rejected_without_panic() {
+    // deposits near `Decimal::MAX` would overflow the balance from the 11th on
+    cmd()
+        .arg(fixture("huge_amounts.csv"))
+        .arg("--verify")
+        .assert()
+        .success()
+        .stdout(
+            "client,available,held,total,locked
+1,12.5,0,12.5,false
+",
+        )
+        .stderr(predicate::str::contains("Error reading event").count(11));
+}
+
+#[test]
+fn test_reports_on_stderr() {
+    cmd()
+        .arg(fixture("transactions.csv"))
+        .args(["--trial-balance", "--verify"])
+        .assert()
+        .success()
+        .stdout(SNAPSHOTS)
+        .stderr(predicate::str::contains("account,debit,credit"))
+        .stderr(predicate::str::contains("Audit passed"));
+}
+
+#[test]
+fn test_open_disputes() {
+    cmd()
+        .arg(fixture("transactions.csv"))
+        .arg("open-disputes")
+        .assert()
+        .success()
+        .stdout(
+            "client,tx,type,amount,disputed,disputed_at,age_secs,age_events
+3,104,deposit,10,10,,,0
+",
+        );
+}
+
+#[test]
+fn test_missing_file() {
+    cmd()
+        .arg(fixture("missing.csv"))
+        .assert()
+        .failure()
+        .stdout("")
+        .stderr(predicate::str::contains("No such file"));
+}
+
+#[test]
+fn test_missing_config_file() {
+    cmd()
+        .arg(fixture("transactions.csv"))
+        .args(["--config", "missing.toml"])
+        .assert()
+        .failure()
+        .stdout("");
+}
+
+#[test]
+fn test_wrong_argument_count() {
+    cmd()
+        .assert()
+        .failure()
+        .code(2)
+        .stderr(predicate::str::contains("Usage"));
+    cmd()
+        .arg(fixture("transactions.csv"))
+        .arg(fixture("invalid.csv"))
+        .assert()
+        .failure()
+        .code(2)
+        .stdout("");
+}
+
+#[test]
+fn test_invalid_arguments() {
+    cmd()
+        .arg(fixture("transactions.csv"))
+        .args(["--shards", "0"])
+        .assert()
+        .failure()
+        .code(2);
+    // Note: chunk size only applies to parallel parsing
+    cmd()
+        .arg(fixture("transactions.csv"))
+        .args(["--chunk-size", "8"])
+        .assert()
+        .failure()
+        .code(2);
+    cmd()
+        .arg(fixture("transactions.csv"))
+        .arg("--bogus")
+        .assert()
+        .failure()
+        .code(2);
+}
@@ -0,0 +1,234 @@
+//! Property based tests of `InMemoryPaymentEngine`, checking invariants on arbitrary event sequences,
+//! and differentially against `Model`, a deliberately naive reference of the spec.
+use payments_engine::{
+    account::AccountSnapshot,
+    decimal::PositiveDecimal,
+    payment_engine::{InMemoryPaymentEngine, PaymentEngine},
+    txn::TxnType,
+    types::{ClientId, TxnEvent, TxnEventDetail, TxnId},
+};
+use proptest::prelude::*;
+use rust_decimal::Decimal;
+use std::collections::BTreeMap;
+
+const CLIENTS: ClientId = 4;
+// Note: few ids, so events often refer to existing transactions
+const TXN_IDS: TxnId = 24;
+
+fn amount() -> impl Strategy<Value = PositiveDecimal> {
+    (1..1_000_000_i64, 0..=4_u32).prop_map(|(value, scale)| {
+        Decimal::new(value, scale)
+            .try_into()
+            .expect("positive amount")
+    })
+}
+
+fn event() -> impl Strategy<Value = TxnEvent> {
+    let detail = prop_oneof![
+        4 => amount().prop_map(|amount| TxnEventDetail::Deposit { amount }),
+        3 => amount().prop_map(|amount| TxnEventDetail::Withdrawal { amount }),
+        3 => proptest::option::weighted(0.3, amount())
+            .prop_map(|amount| TxnEventDetail::Dispute { amount }),
+        2 => Just(TxnEventDetail::Resolve),
+        1 => proptest::option::weighted(0.3, amount())
+            .prop_map(|amount| TxnEventDetail::Chargeback { amount }),
+    ];
+    (0..CLIENTS, 0..TXN_IDS, detail).prop_map(|(client_id, txn_id, detail)| TxnEvent {
+        client_id,
+        txn_id,
+        timestamp: None,
+        detail,
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Settled,
+    Disputed,
+    Resolved,
+    ChargedBack,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ModelTxn {
+    /// +1 for deposits, -1 for withdrawals
+    sign: Decimal,
+    amount: Decimal,
+    state: State,
+    disputed: Decimal,
+}
+
+#[derive(Debug, Default)]
+struct ModelAccount {
+    available: Decimal,
+    held: Decimal,
+    locked: bool,
+    txns: BTreeMap<TxnId, ModelTxn>,
+}
+
+/// Reference of the engine's default policies, for deposits, withdrawals and disputes.
+#[derive(Debug, Default)]
+struct Model {
+    accs: BTreeMap<ClientId, ModelAccount>,
+}
+
+impl Model {
+    /// Applies the event, returning whether it was accepted.
+    fn apply(&mut self, event: &TxnEvent) -> bool {
+        let txn_id = event.txn_id;
+        match &event.detail {
+            TxnEventDetail::Deposit { amount } => {
+                let acc = self.accs.entry(event.client_id).or_default();
+                Self::record(acc, txn_id, Decimal::ONE, **amount)
+            }
+            TxnEventDetail::Withdrawal { amount } => match self.accs.get_mut(&event.client_id) {
+                Some(acc) if !acc.locked && acc.available >= **amount => {
+                    Self::record(acc, txn_id, Decimal::NEGATIVE_ONE, **amount)
+                }
+                _ => false,
+            },
+            detail => {
+                let Some(acc) = self.accs.get_mut(&event.client_id) else {
+                    return false;
+                };
+                if acc.locked {
+                    return false;
+                }
+                let Some(txn) = acc.txns.get_mut(&txn_id) else {
+                    return false;
+                };
+                match (detail, txn.state) {
+                    (
+                        TxnEventDetail::Dispute { amount },
+                        State::Settled | State::Resolved | State::Disputed,
+                    ) => {
+                        let undisputed = txn.amount - txn.disputed;
+                        let portion = amount.map_or(undisputed, |amount| *amount);
+                        if undisputed.is_zero() || portion > undisputed {
+                            return false;
+                        }
+                        txn.disputed += portion;
+                        txn.state = State::Disputed;
+                        acc.held += txn.sign * portion;
+                        acc.available -= txn.sign * portion;
+                    }
+                    (TxnEventDetail::Resolve, State::Disputed) => {
+                        acc.held -= txn.sign * txn.disputed;
+                        acc.available += txn.sign * txn.disputed;
+                        txn.disputed = Decimal::ZERO;
+                        txn.state = State::Resolved;
+                    }
+                    (TxnEventDetail::Chargeback { amount }, State::Disputed) => {
+                        let portion = amount.map_or(txn.disputed, |amount| *amount);
+                        if portion > txn.disputed {
+                            return false;
+                        }
+                        // the portion leaves the account, the rest of the dispute is released
+                        acc.held -= txn.sign * txn.disputed;
+                        acc.available += txn.sign * (txn.disputed - portion);
+                        txn.disputed = Decimal::ZERO;
+                        txn.state = State::ChargedBack;
+                        acc.locked = true;
+                    }
+                    _ => return false,
+                }
+                true
+            }
+        }
+    }
+
+    /// Records a deposit/withdrawal, overwriting only settled transactions of the same id.
+    fn record(acc: &mut ModelAccount, txn_id: TxnId, sign: Decimal, amount: Decimal) -> bool {
+        if acc
+            .txns
+            .get(&txn_id)
+            .is_some_and(|txn| txn.state != State::Settled)
+        {
+            return false;
+        }
+        acc.txns.insert(
+            txn_id,
+            ModelTxn {
+                sign,
+                amount,
+                state: State::Settled,
+                disputed: Decimal::ZERO,
+            },
+        );
+        acc.available += sign * amount;
+        true
+    }
+
+    fn snapshots(&self) -> Vec<AccountSnapshot> {
+        self.accs
+            .iter()
+            .map(|(&client_id, acc)| AccountSnapshot {
+                client_id,
+                available: acc.available,
+                held: acc.held,
+                total: acc.available + acc.held,
+                locked: acc.locked,
+            })
+            .collect()
+    }
+}
+
+/// Checks the engine invariants, which hold after every event regardless of the sequence.
+fn check_invariants(engine: &InMemoryPaymentEngine) -> Result<(), TestCaseError> {
+    for snapshot in engine.snapshots().unwrap() {
+        prop_assert_eq!(snapshot.total, snapshot.available + snapshot.held);
+        let disputed = engine
+            .disputed_transactions(snapshot.client_id)
+            .unwrap()
+            .into_iter()
+            .map(|view| match view.txn_type {
+                TxnType::Withdrawal => -view.disputed,
+                TxnType::TransferOut { .. } => Decimal::ZERO,
+                TxnType::Deposit | TxnType::TransferIn { .. } => view.disputed,
+            })
+            .sum::<Decimal>();
+        prop_assert_eq!(snapshot.held, disputed, "client {}", snapshot.client_id);
+    }
+    prop_assert!(engine.audit().unwrap().is_empty());
+    prop_assert!(engine.trial_balance().unwrap().is_balanced());
+    Ok(())
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(512))]
+
+    #[test]
+    fn test_invariants(events in prop::collection::vec(event(), 1..200)) {
+        let mut engine = InMemoryPaymentEngine::default();
+        for event in events {
+            let before = engine.snapshots().unwrap();
+            let was_locked = engine
+                .account(event.client_id)
+                .unwrap()
+                .is_some_and(|acc| acc.locked);
+            let is_withdrawal = matches!(event.detail, TxnEventDetail::Withdrawal { .. });
+
+            let res = engine.add_event(event.clone());
+
+            if was_locked && is_withdrawal {
+                prop_assert!(res.is_err(), "locked account withdrew: {:?}", event);
+            }
+            if res.is_err() {
+                prop_assert_eq!(&before, &engine.snapshots().unwrap(), "rejected {:?}", event);
+            }
+            check_invariants(&engine)?;
+        }
+    }
+
+    #[test]
+    fn test_matches_model(events in prop::collection::vec(event(), 1..200)) {
+        let mut engine = InMemoryPaymentEngine::default();
+        let mut model = Model::default();
+        for event in events {
+            let accepted = model.apply(&event);
+            let res = engine.add_event(event.clone());
+            prop_assert_eq!(accepted, res.is_ok(), "{:?}: {:?}", event, res);
+            prop_assert_eq!(model.snapshots(), engine.snapshots().unwrap(), "after {:?}", event);
+        }
+    }
+}
@@ -0,0 +1,198 @@
+//! Golden file scenarios: every `tests/scenarios/<name>/input.csv` is run against each engine,
+//! comparing account snapshots with `expected.csv`, and rejected events with `expected_rejects.csv` if present.
+//!
+//! Run with `UPDATE_SCENARIOS=1` to regenerate the expected files from the output of `InMemoryPaymentEngine`.
+use anyhow::Context;
+use payments_engine::{
+    account::AccountSnapshot,
+    concurrent::ConcurrentPaymentEngine,
+    config::EngineConfig,
+    error::EngineError,
+    ingest::{OTHER_ERROR, PARSE_ERROR},
+    payment_engine::{InMemoryPaymentEngine, PaymentEngine},
+    sharded::ShardedPaymentEngine,
+    types::{ClientId, TxnEvent, TxnId},
+    util::{read_csv_file, to_csv_string},
+};
+use serde::Serialize;
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+const UPDATE_ENV: &str = "UPDATE_SCENARIOS";
+
+/// Adapts engines to the scenarios, as `ConcurrentPaymentEngine` does not implement `PaymentEngine`.
+trait ScenarioEngine {
+    fn add_event(&mut self, event: TxnEvent) -> anyhow::Result<()>;
+
+    fn snapshots(&self) -> anyhow::Result<Vec<AccountSnapshot>>;
+}
+
+impl<T: PaymentEngine> ScenarioEngine for T {
+    fn add_event(&mut self, event: TxnEvent) -> anyhow::Result<()> {
+        PaymentEngine::add_event(self, event)
+    }
+
+    fn snapshots(&self) -> anyhow::Result<Vec<AccountSnapshot>> {
+        PaymentEngine::snapshots(self)
+    }
+}
+
+/// Feeds events sequentially, concurrency being covered by the engine's own tests.
+struct Concurrent(ConcurrentPaymentEngine);
+
+impl ScenarioEngine for Concurrent {
+    fn add_event(&mut self, event: TxnEvent) -> anyhow::Result<()> {
+        self.0.add_event(event)
+    }
+
+    fn snapshots(&self) -> anyhow::Result<Vec<AccountSnapshot>> {
+        self.0.snapshots()
+    }
+}
+
+/// Engines every scenario runs against, the first one generating expected files in update mode.
+fn engines() -> anyhow::Result<Vec<(&'static str, Box<dyn ScenarioEngine>)>> {
+    Ok(vec![
+        ("in_memory", Box::new(InMemoryPaymentEngine::default())),
+        (
+            "sharded",
+            Box::new(ShardedPaymentEngine::new(4, EngineConfig::default())),
+        ),
+        (
+            "concurrent",
+            Box::new(Concurrent(ConcurrentPaymentEngine::new(
+                EngineConfig::default(),
+            )?)),
+        ),
+    ])
+}
+
+/// Rejected event, by line of the input.
+#[derive(Serialize, Debug)]
+struct Reject {
+    line: usize,
+    r#type: Option<&'static str>,
+    client: Option<ClientId>,
+    tx: Option<TxnId>,
+    reason: &'static str,
+}
+
+/// Output of a scenario, as written to the expected files.
+#[derive(Debug)]
+struct Outcome {
+    snapshots: String,
+    rejects: String,
+}
+
+fn run(engine: &mut dyn ScenarioEngine, input: &Path) -> anyhow::Result<Outcome> {
+    let mut rejects = vec![];
+    for (index, event) in read_csv_file(fs::File::open(input)?).enumerate() {
+        // Note: header on line 1, records are assumed not to span lines
+        let line = index + 2;
+        let event = match event {
+            Ok(event) => event,
+            Err(_) => {
+                rejects.push(Reject {
+                    line,
+                    r#type: None,
+                    client: None,
+                    tx: None,
+                    reason: PARSE_ERROR,
+                });
+                continue;
+            }
+        };
+        let (r#type, client, tx) = (event.detail.name(), event.client_id, event.txn_id);
+        if let Err(err) = engine.add_event(event) {
+            rejects.push(Reject {
+                line,
+                r#type: Some(r#type),
+                client: Some(client),
+                tx: Some(tx),
+                reason: err
+                    .downcast_ref::<EngineError>()
+                    .map_or(OTHER_ERROR, EngineError::reason),
+            });
+        }
+    }
+    Ok(Outcome {
+        snapshots: to_csv_string(&engine.snapshots()?)?,
+        rejects: to_csv_string(&rejects)?,
+    })
+}
+
+fn read_expected(path: &Path) -> anyhow::Result<String> {
+    let contents =
+        fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+    Ok(contents.trim().to_owned())
+}
+
+fn write_expected(path: &Path, contents: &str) -> anyhow::Result<()> {
+    Ok(fs::write(path, format!("{contents}\n"))?)
+}
+
+fn scenario_dirs() -> anyhow::Result<Vec<PathBuf>> {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/scenarios");
+    let mut dirs = fs::read_dir(root)?
+        .map(|entry| Ok(entry?.path()))
+        .filter(|path| {
+            path.as_ref()
+                .map_or(true, |path| path.join("input.csv").is_file())
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    dirs.sort();
+    Ok(dirs)
+}
+
+#[test]
+fn test_scenarios() -> anyhow::Result<()> {
+    let update = env::var_os(UPDATE_ENV).is_some();
+    let dirs = scenario_dirs()?;
+    assert!(!dirs.is_empty(), "no scenarios found");
+
+    let mut failures = vec![];
+    for dir in &dirs {
+        let name = dir.file_name().unwrap().to_string_lossy();
+        let expected_path = dir.join("expected.csv");
+        let rejects_path = dir.join("expected_rejects.csv");
+        for (index, (engine_name, mut engine)) in engines()?.into_iter().enumerate() {
+            let outcome = run(engine.as_mut(), &dir.join("input.csv"))?;
+            if update && index == 0 {
+                write_expected(&expected_path, &outcome.snapshots)?;
+                if outcome.rejects.is_empty() {
+                    if rejects_path.exists() {
+                        fs::remove_file(&rejects_path)?;
+                    }
+                } else {
+                    write_expected(&rejects_path, &outcome.rejects)?;
+                }
+            }
+
+            let expected = read_expected(&expected_path)?;
+            if outcome.snapshots != expected {
+                failures.push(format!(
+                    "{name} ({engine_name}): snapshots\n--- expected\n{expected}\n--- actual\n{}",
+                    outcome.snapshots
+                ));
+            }
+            if rejects_path.exists() {
+                let expected = read_expected(&rejects_path)?;
+                if outcome.rejects != expected {
+                    failures.push(format!(
+                        "{name} ({engine_name}): rejects\n--- expected\n{expected}\n--- actual\n{}",
+                        outcome.rejects
+                    ));
+                }
+            }
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "{} check(s) failed, run with {UPDATE_ENV}=1 to regenerate expected files\n\n{}",
+        failures.len(),
+        failures.join("\n\n")
+    );
+    Ok(())
+}